@@ -1,14 +1,19 @@
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use image::{codecs::jpeg::JpegEncoder, DynamicImage};
 use std::io::Cursor;
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
 
-use crate::models::{EditState, ExportOptions, ExportResult, FolderContents, ImageFile, Flag};
-use crate::services::{filesystem, thumbnail, xmp, export, image_processor, ai_processor};
+use crate::models::{ContactSheetOptions, ContactSheetResult, EditHistory, EditState, ExportJobStatus, ExportOptions, ExportRecipe, ExportResult, FieldMask, FolderContents, ImageFile, Flag, LensInfo, LookInfo, PresetInfo, SampleRegion, Snapshot, Watermark, WhiteBalancePick};
+use crate::services::{contact_sheet, filesystem, history, thumbnail, xmp, export, image_processor, ai_processor, white_balance, lens, effects, lut, presets, watermark};
+use crate::services::export_jobs::{self, ExportJob};
 
 const MAX_CACHE_SIZE: usize = 10;
 
+/// How long a finished export job can still be read with `get_export_job`.
+/// Older ones are dropped when the next export starts.
+const FINISHED_JOB_TTL: std::time::Duration = std::time::Duration::from_secs(600);
+
 pub struct ImageCache {
     images: HashMap<String, DynamicImage>,
     order: Vec<String>,
@@ -42,6 +47,15 @@ pub struct AppState {
     pub files: Mutex<HashMap<String, ImageFile>>,
     pub edit_states: Mutex<HashMap<String, EditState>>,
     pub image_cache: Mutex<ImageCache>,
+    /// Interpolated lens profile per file and chosen profile, so previews do
+    /// not re-read EXIF on every slider change.
+    pub lens_calibrations: Mutex<HashMap<String, Option<lens::ShotCalibration>>>,
+    /// Edits and fields taken by `copy_edits`, for `paste_edits`.
+    pub edit_clipboard: Mutex<Option<(EditState, Vec<String>)>>,
+    /// Export jobs by id. Finished jobs are kept for `FINISHED_JOB_TTL` so
+    /// their results can be read even when the export ends before the UI
+    /// listens for its events.
+    pub export_jobs: Mutex<HashMap<String, Arc<ExportJob>>>,
 }
 
 impl Default for AppState {
@@ -50,13 +64,18 @@ impl Default for AppState {
             files: Mutex::new(HashMap::new()),
             edit_states: Mutex::new(HashMap::new()),
             image_cache: Mutex::new(ImageCache::new()),
+            lens_calibrations: Mutex::new(HashMap::new()),
+            edit_clipboard: Mutex::new(None),
+            export_jobs: Mutex::new(HashMap::new()),
         }
     }
 }
 
 #[tauri::command]
 pub async fn open_folder(path: String, state: State<'_, AppState>) -> Result<FolderContents, String> {
-    let files = filesystem::scan_directory(std::path::Path::new(&path))?;
+    let mut files = filesystem::scan_directory(std::path::Path::new(&path))?;
+    files.extend(filesystem::find_virtual_copies(&files));
+    files.sort_by(|a, b| a.filename.cmp(&b.filename).then(a.virtual_copy.cmp(&b.virtual_copy)));
     let edit_states = filesystem::load_sidecars(&files);
     let thumbnail_dir = filesystem::get_thumbnail_dir().to_string_lossy().to_string();
 
@@ -97,7 +116,7 @@ pub async fn get_thumbnail(file_id: String, state: State<'_, AppState>) -> Resul
 #[tauri::command]
 pub async fn get_preview(
     file_id: String,
-    edits: EditState,
+    mut edits: EditState,
     max_size: u32,
     state: State<'_, AppState>,
 ) -> Result<Vec<u8>, String> {
@@ -107,7 +126,11 @@ pub async fn get_preview(
         file.path.clone()
     };
 
-    let cache_key = format!("{}_{}", file_id, max_size);
+    if edits.grain.seed == 0 {
+        edits.grain.seed = effects::seed_for_file(&path);
+    }
+
+    let cache_key = format!("{}_{}_{:?}", file_id, max_size, edits.highlight_reconstruction);
     let img = {
         let cache = state.image_cache.lock().unwrap();
         cache.get(&cache_key).cloned()
@@ -116,7 +139,14 @@ pub async fn get_preview(
     let img = match img {
         Some(cached) => cached,
         None => {
-            let loaded = thumbnail::load_image(&path)?;
+            let (loaded, dimensions) =
+                thumbnail::develop_image(&path, edits.highlight_reconstruction, Some(max_size))?;
+            {
+                let mut files = state.files.lock().unwrap();
+                if let Some(file) = files.get_mut(&file_id) {
+                    file.dimensions = Some(dimensions);
+                }
+            }
             let resized = image_processor::resize_to_fit(loaded, max_size);
             {
                 let mut cache = state.image_cache.lock().unwrap();
@@ -126,13 +156,31 @@ pub async fn get_preview(
         }
     };
 
+    // Lens geometry applies to the full frame, before crop
+    let calibration = lens_calibration(&state, &file_id, &path, &edits);
+    let img = match lens::LensCorrector::new(&edits.lens, calibration.as_ref()) {
+        Some(corrector) => corrector.apply(img),
+        None => img,
+    };
+
+    let img_width = img.width();
     let cropped = if let Some(ref crop) = edits.crop {
         image_processor::apply_crop(img, crop)
     } else {
         img
     };
 
-    let processed = image_processor::apply_edits(cropped, &edits);
+    // Radius-based tools are specified in source pixels
+    let scale = {
+        let files = state.files.lock().unwrap();
+        files
+            .get(&file_id)
+            .and_then(|f| f.dimensions.as_ref())
+            .map(|d| img_width as f32 / d.width.max(1) as f32)
+            .unwrap_or(1.0)
+    };
+
+    let processed = image_processor::apply_edits_at_scale(cropped, &edits, scale);
     let rotated = image_processor::rotate_image(processed, edits.rotation);
 
     let rgb = rotated.to_rgb8();
@@ -145,20 +193,99 @@ pub async fn get_preview(
 }
 
 #[tauri::command]
-pub async fn save_edits(file_id: String, edits: EditState, state: State<'_, AppState>) -> Result<(), String> {
+pub async fn save_edits(
+    file_id: String,
+    edits: EditState,
+    label: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
     let files = state.files.lock().unwrap();
     let file = files.get(&file_id).ok_or("File not found")?;
-    let xmp_path = filesystem::get_xmp_path(&file.path);
+    let path = filesystem::get_sidecar_path(file);
     drop(files);
 
-    xmp::save_xmp_file(&xmp_path, &edits)?;
+    xmp::save_xmp_file(&path, &edits)?;
 
     let mut states = state.edit_states.lock().unwrap();
-    states.insert(file_id, edits);
+    let previous = states.insert(file_id, edits.clone()).unwrap_or_default();
+    drop(states);
 
+    record_history(&path, &previous, &edits, label.as_deref().unwrap_or("Edit"));
     Ok(())
 }
 
+fn record_history(sidecar_path: &str, previous: &EditState, edits: &EditState, label: &str) {
+    if let Err(e) = history::record(sidecar_path, previous, edits, label) {
+        tracing::warn!("Failed to record history for {}: {}", sidecar_path, e);
+    }
+}
+
+#[tauri::command]
+pub async fn get_history(file_id: String, state: State<'_, AppState>) -> Result<EditHistory, String> {
+    let path = sidecar_path(&state, &file_id)?;
+    Ok(history::load_history(&path))
+}
+
+#[tauri::command]
+pub async fn undo_edits(file_id: String, state: State<'_, AppState>) -> Result<EditState, String> {
+    let path = sidecar_path(&state, &file_id)?;
+    history::undo(&path, |edits| restore_edits(&state, &file_id, &path, edits))
+}
+
+#[tauri::command]
+pub async fn redo_edits(file_id: String, state: State<'_, AppState>) -> Result<EditState, String> {
+    let path = sidecar_path(&state, &file_id)?;
+    history::redo(&path, |edits| restore_edits(&state, &file_id, &path, edits))
+}
+
+#[tauri::command]
+pub async fn jump_to_history(file_id: String, index: usize, state: State<'_, AppState>) -> Result<EditState, String> {
+    let path = sidecar_path(&state, &file_id)?;
+    history::go_to(&path, index, |edits| restore_edits(&state, &file_id, &path, edits))
+}
+
+#[tauri::command]
+pub async fn create_snapshot(file_id: String, name: String, state: State<'_, AppState>) -> Result<Snapshot, String> {
+    let path = sidecar_path(&state, &file_id)?;
+    let edits = state.edit_states.lock().unwrap().get(&file_id).cloned().unwrap_or_default();
+    history::create_snapshot(&path, &name, &edits)
+}
+
+#[tauri::command]
+pub async fn restore_snapshot(file_id: String, name: String, state: State<'_, AppState>) -> Result<EditState, String> {
+    let path = sidecar_path(&state, &file_id)?;
+    let snapshot = history::find_snapshot(&path, &name)?;
+    let previous = state.edit_states.lock().unwrap().get(&file_id).cloned().unwrap_or_default();
+    let edits = restore_edits(&state, &file_id, &path, snapshot.edits)?;
+    record_history(&path, &previous, &edits, &format!("Snapshot: {}", snapshot.name));
+    Ok(edits)
+}
+
+#[tauri::command]
+pub async fn delete_snapshot(file_id: String, name: String, state: State<'_, AppState>) -> Result<(), String> {
+    let path = sidecar_path(&state, &file_id)?;
+    history::delete_snapshot(&path, &name)
+}
+
+fn sidecar_path(state: &AppState, file_id: &str) -> Result<String, String> {
+    let files = state.files.lock().unwrap();
+    let file = files.get(file_id).ok_or("File not found")?;
+    Ok(filesystem::get_sidecar_path(file))
+}
+
+/// Makes an earlier edit state current. Rating and flag are not part of the
+/// history, so the current ones are kept.
+fn restore_edits(state: &AppState, file_id: &str, path: &str, mut edits: EditState) -> Result<EditState, String> {
+    let mut states = state.edit_states.lock().unwrap();
+    if let Some(current) = states.get(file_id) {
+        edits.rating = current.rating;
+        edits.flag = current.flag;
+    }
+    xmp::save_xmp_file(path, &edits)?;
+    states.insert(file_id.to_string(), edits.clone());
+    Ok(edits)
+}
+
 #[tauri::command]
 pub async fn set_rating(file_id: String, rating: u8, state: State<'_, AppState>) -> Result<(), String> {
     let mut states = state.edit_states.lock().unwrap();
@@ -169,7 +296,7 @@ pub async fn set_rating(file_id: String, rating: u8, state: State<'_, AppState>)
 
     let files = state.files.lock().unwrap();
     if let Some(file) = files.get(&file_id) {
-        let xmp_path = filesystem::get_xmp_path(&file.path);
+        let xmp_path = filesystem::get_sidecar_path(file);
         drop(files);
         xmp::save_xmp_file(&xmp_path, &updated)?;
     }
@@ -193,7 +320,7 @@ pub async fn set_flag(file_id: String, flag: String, state: State<'_, AppState>)
 
     let files = state.files.lock().unwrap();
     if let Some(file) = files.get(&file_id) {
-        let xmp_path = filesystem::get_xmp_path(&file.path);
+        let xmp_path = filesystem::get_sidecar_path(file);
         drop(files);
         xmp::save_xmp_file(&xmp_path, &updated)?;
     }
@@ -202,37 +329,386 @@ pub async fn set_flag(file_id: String, flag: String, state: State<'_, AppState>)
 }
 
 #[tauri::command]
-pub async fn export_images(
+pub async fn create_virtual_copy(file_id: String, state: State<'_, AppState>) -> Result<ImageFile, String> {
+    let files = state.files.lock().unwrap();
+    let source = files.get(&file_id).ok_or("File not found")?.clone();
+    let taken: Vec<u32> = files
+        .values()
+        .filter(|f| f.path == source.path)
+        .filter_map(|f| f.virtual_copy)
+        .collect();
+    drop(files);
+
+    // Skip numbers whose sidecar exists, e.g. from the other half of a RAW+JPEG pair
+    let copy = (1..)
+        .map(|n| ImageFile {
+            id: uuid::Uuid::new_v4().to_string(),
+            virtual_copy: Some(n),
+            ..source.clone()
+        })
+        .find(|c| {
+            !taken.contains(&c.virtual_copy.unwrap_or_default())
+                && !std::path::Path::new(&filesystem::get_sidecar_path(c)).exists()
+        })
+        .ok_or("No free copy number")?;
+
+    // A copy starts from the source's current edits
+    let edits = state.edit_states.lock().unwrap().get(&file_id).cloned().unwrap_or_default();
+    xmp::save_xmp_file(&filesystem::get_sidecar_path(&copy), &edits)?;
+
+    state.edit_states.lock().unwrap().insert(copy.id.clone(), edits);
+    state.files.lock().unwrap().insert(copy.id.clone(), copy.clone());
+    Ok(copy)
+}
+
+#[tauri::command]
+pub async fn delete_virtual_copy(file_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    let files = state.files.lock().unwrap();
+    let file = files.get(&file_id).ok_or("File not found")?;
+    if file.virtual_copy.is_none() {
+        return Err("Not a virtual copy".to_string());
+    }
+    let sidecar = filesystem::get_sidecar_path(file);
+    drop(files);
+
+    for path in [sidecar.clone(), filesystem::get_history_path(&sidecar)] {
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("Delete failed: {}", e));
+            }
+            _ => {}
+        }
+    }
+
+    state.files.lock().unwrap().remove(&file_id);
+    state.edit_states.lock().unwrap().remove(&file_id);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn pick_white_balance(
+    file_id: String,
+    region: SampleRegion,
+    state: State<'_, AppState>,
+) -> Result<WhiteBalancePick, String> {
+    let files = state.files.lock().unwrap();
+    let file = files.get(&file_id).ok_or("File not found")?;
+    let path = file.path.clone();
+    drop(files);
+
+    let mode = state
+        .edit_states
+        .lock()
+        .unwrap()
+        .get(&file_id)
+        .map(|e| e.highlight_reconstruction)
+        .unwrap_or_default();
+
+    let (img, _) = thumbnail::develop_image(&path, mode, Some(1024))?;
+    let resized = image_processor::resize_to_fit(img, 1024);
+    let average = image_processor::average_linear_rgb(&resized, &region)
+        .ok_or("Sample region contains no usable pixels")?;
+
+    let (temp, tint) = white_balance::temp_tint_for_neutral(average);
+    Ok(WhiteBalancePick {
+        white_balance_temp: temp.round(),
+        white_balance_tint: tint.round(),
+    })
+}
+
+fn lens_calibration(
+    state: &State<'_, AppState>,
+    file_id: &str,
+    path: &str,
+    edits: &EditState,
+) -> Option<lens::ShotCalibration> {
+    if !edits.lens.profile_enabled {
+        return None;
+    }
+    let key = format!("{}_{}", file_id, edits.lens.profile_name.as_deref().unwrap_or(""));
+    if let Some(cached) = state.lens_calibrations.lock().unwrap().get(&key) {
+        return cached.clone();
+    }
+    let calibration = lens::shot_calibration(path, &edits.lens);
+    state.lens_calibrations.lock().unwrap().insert(key, calibration.clone());
+    calibration
+}
+
+#[tauri::command]
+pub async fn detect_lens(file_id: String, state: State<'_, AppState>) -> Result<LensInfo, String> {
+    let files = state.files.lock().unwrap();
+    let file = files.get(&file_id).ok_or("File not found")?;
+    let path = file.path.clone();
+    drop(files);
+
+    Ok(lens::detect_lens(&path, None))
+}
+
+#[tauri::command]
+pub async fn list_looks() -> Result<Vec<LookInfo>, String> {
+    Ok(lut::list_looks())
+}
+
+#[tauri::command]
+pub async fn import_look(path: String) -> Result<LookInfo, String> {
+    lut::import_look(std::path::Path::new(&path))
+}
+
+#[tauri::command]
+pub async fn delete_look(name: String) -> Result<(), String> {
+    lut::delete_look(&name)
+}
+
+#[tauri::command]
+pub async fn list_presets() -> Result<Vec<PresetInfo>, String> {
+    Ok(presets::list_presets())
+}
+
+#[tauri::command]
+pub async fn save_preset(name: String, edits: EditState, fields: Vec<String>) -> Result<PresetInfo, String> {
+    presets::save_preset(&name, &edits, &fields)
+}
+
+#[tauri::command]
+pub async fn import_preset(path: String) -> Result<PresetInfo, String> {
+    presets::import_preset(std::path::Path::new(&path))
+}
+
+#[tauri::command]
+pub async fn delete_preset(name: String) -> Result<(), String> {
+    presets::delete_preset(&name)
+}
+
+#[tauri::command]
+pub async fn apply_preset(
+    name: String,
     file_ids: Vec<String>,
-    destination: String,
-    options: ExportOptions,
     state: State<'_, AppState>,
-) -> Result<Vec<ExportResult>, String> {
+) -> Result<HashMap<String, EditState>, String> {
+    let preset = presets::load_preset(&name)?;
+
+    let label = format!("Preset: {}", preset.name.as_deref().unwrap_or(&name));
+    merge_into_files(&state, &preset.state, &preset.settings, &file_ids, &label)
+}
+
+#[tauri::command]
+pub async fn copy_edits(file_id: String, mask: FieldMask, state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let fields = presets::resolve_mask(&mask)?;
+    if !state.files.lock().unwrap().contains_key(&file_id) {
+        return Err("File not found".to_string());
+    }
+    let edits = state.edit_states.lock().unwrap().get(&file_id).cloned().unwrap_or_default();
+
+    *state.edit_clipboard.lock().unwrap() = Some((edits, fields.clone()));
+    Ok(fields)
+}
+
+#[tauri::command]
+pub async fn paste_edits(file_ids: Vec<String>, state: State<'_, AppState>) -> Result<HashMap<String, EditState>, String> {
+    let clipboard = state.edit_clipboard.lock().unwrap().clone();
+    let (edits, fields) = clipboard.ok_or("Nothing to paste")?;
+    merge_into_files(&state, &edits, &fields, &file_ids, "Paste settings")
+}
+
+#[tauri::command]
+pub async fn sync_edits(
+    source_id: String,
+    mask: FieldMask,
+    file_ids: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, EditState>, String> {
+    let fields = presets::resolve_mask(&mask)?;
+    if !state.files.lock().unwrap().contains_key(&source_id) {
+        return Err("File not found".to_string());
+    }
+    let edits = state.edit_states.lock().unwrap().get(&source_id).cloned().unwrap_or_default();
+
+    let targets: Vec<String> = file_ids.into_iter().filter(|id| *id != source_id).collect();
+    merge_into_files(&state, &edits, &fields, &targets, "Sync settings")
+}
+
+/// Merges `fields` of `source` into the edits of each file and saves them.
+/// Either every sidecar and in-memory state changes or none do.
+fn merge_into_files(
+    state: &AppState,
+    source: &EditState,
+    fields: &[String],
+    file_ids: &[String],
+    label: &str,
+) -> Result<HashMap<String, EditState>, String> {
+    let files = state.files.lock().unwrap();
+    let paths = file_ids
+        .iter()
+        .map(|id| files.get(id).map(filesystem::get_sidecar_path).ok_or("File not found"))
+        .collect::<Result<Vec<_>, _>>()?;
+    drop(files);
+
+    // A RAW+JPEG pair shares one sidecar, so it is written once, from the
+    // first file of the pair given, and both files take its edits
+    let mut states = state.edit_states.lock().unwrap();
+    let mut sidecars: Vec<(String, EditState)> = Vec::new();
+    let mut previous = Vec::new();
+    for (id, path) in file_ids.iter().zip(&paths) {
+        if sidecars.iter().any(|(p, _)| p == path) {
+            continue;
+        }
+        let current = states.get(id).cloned().unwrap_or_default();
+        let merged = presets::merge_fields(&current, source, fields)?;
+        sidecars.push((path.clone(), merged));
+        previous.push(current);
+    }
+    xmp::save_xmp_files(&sidecars)?;
+
+    for ((path, edits), previous) in sidecars.iter().zip(&previous) {
+        record_history(path, previous, edits, label);
+    }
+    let mut updated = HashMap::new();
+    for (id, path) in file_ids.iter().zip(&paths) {
+        if let Some((_, edits)) = sidecars.iter().find(|(p, _)| p == path) {
+            states.insert(id.clone(), edits.clone());
+            updated.insert(id.clone(), edits.clone());
+        }
+    }
+    Ok(updated)
+}
+
+#[tauri::command]
+pub async fn list_watermarks() -> Result<Vec<Watermark>, String> {
+    Ok(watermark::list_watermarks())
+}
+
+#[tauri::command]
+pub async fn save_watermark(watermark: Watermark) -> Result<Watermark, String> {
+    watermark::save_watermark(&watermark)
+}
+
+#[tauri::command]
+pub async fn delete_watermark(name: String) -> Result<(), String> {
+    watermark::delete_watermark(&name)
+}
+
+/// Copies the files and edits to export, so exporting holds no locks.
+fn export_snapshot(state: &AppState, file_ids: &[String]) -> Vec<(String, Option<(ImageFile, EditState)>)> {
     let files = state.files.lock().unwrap();
     let states = state.edit_states.lock().unwrap();
+    file_ids
+        .iter()
+        .map(|id| {
+            let entry = files
+                .get(id)
+                .map(|file| (file.clone(), states.get(id).cloned().unwrap_or_default()));
+            (id.clone(), entry)
+        })
+        .collect()
+}
 
-    let mut results = Vec::new();
+/// Exports and waits for the results. Takes either `options` or a list of
+/// named `recipes`, which are all written from one render of each file.
+/// `start_export` runs the same export in the background.
+#[tauri::command]
+pub async fn export_images(
+    file_ids: Vec<String>,
+    destination: String,
+    options: Option<ExportOptions>,
+    recipes: Option<Vec<ExportRecipe>>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ExportResult>, String> {
+    let recipes = export::export_recipes(options, recipes)?;
+
+    // Runs on a blocking thread so the async runtime stays free meanwhile
+    let snapshot = export_snapshot(&state, &file_ids);
+    tauri::async_runtime::spawn_blocking(move || {
+        let items = export_jobs::prepare(snapshot, &destination, &recipes);
+        let job = ExportJob::new(items.len() * recipes.len());
+        job.run(items, &recipes, |_| {});
+        job.status().results
+    })
+    .await
+    .map_err(|e| format!("Export failed: {}", e))
+}
 
-    for file_id in file_ids {
-        let file = match files.get(&file_id) {
-            Some(f) => f,
-            None => {
-                results.push(ExportResult {
-                    success: false,
-                    source_id: file_id,
-                    destination_path: None,
-                    error: Some("File not found".to_string()),
-                });
-                continue;
+/// Starts an export job and returns its id. Sends `export_progress` as each
+/// output finishes and `export_complete` with the final status, after which
+/// the job is dropped.
+#[tauri::command]
+pub async fn start_export(
+    file_ids: Vec<String>,
+    destination: String,
+    options: Option<ExportOptions>,
+    recipes: Option<Vec<ExportRecipe>>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let recipes = export::export_recipes(options, recipes)?;
+
+    let snapshot = export_snapshot(&state, &file_ids);
+    let job = Arc::new(ExportJob::new(snapshot.len() * recipes.len()));
+    let job_id = job.id.clone();
+    let mut jobs = state.export_jobs.lock().unwrap();
+    jobs.retain(|_, job| job.finished_for().is_none_or(|age| age < FINISHED_JOB_TTL));
+    jobs.insert(job_id.clone(), job.clone());
+    drop(jobs);
+
+    std::thread::spawn(move || {
+        let items = export_jobs::prepare(snapshot, &destination, &recipes);
+        job.run(items, &recipes, |progress| {
+            if let Err(e) = app.emit("export_progress", progress) {
+                tracing::warn!("Failed to send export progress: {}", e);
             }
-        };
+        });
+        if let Err(e) = app.emit("export_complete", job.status()) {
+            tracing::warn!("Failed to send export completion: {}", e);
+        }
+    });
 
-        let edits = states.get(&file_id).cloned().unwrap_or_default();
-        let result = export::export_image(&file.path, &file_id, &destination, &edits, &options);
-        results.push(result);
-    }
+    Ok(job_id)
+}
 
-    Ok(results)
+fn export_job(state: &AppState, job_id: &str) -> Result<Arc<ExportJob>, String> {
+    state
+        .export_jobs
+        .lock()
+        .unwrap()
+        .get(job_id)
+        .cloned()
+        .ok_or_else(|| format!("Export job not found: {}", job_id))
+}
+
+#[tauri::command]
+pub async fn get_export_job(job_id: String, state: State<'_, AppState>) -> Result<ExportJobStatus, String> {
+    Ok(export_job(&state, &job_id)?.status())
+}
+
+#[tauri::command]
+pub async fn cancel_export(job_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    export_job(&state, &job_id)?.cancel();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn pause_export(job_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    export_job(&state, &job_id)?.set_paused(true);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn resume_export(job_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    export_job(&state, &job_id)?.set_paused(false);
+    Ok(())
+}
+
+/// Renders the files into a paginated contact sheet for proofing.
+#[tauri::command]
+pub async fn create_contact_sheet(
+    file_ids: Vec<String>,
+    destination: String,
+    options: ContactSheetOptions,
+    state: State<'_, AppState>,
+) -> Result<ContactSheetResult, String> {
+    let snapshot = export_snapshot(&state, &file_ids);
+    tauri::async_runtime::spawn_blocking(move || contact_sheet::create_contact_sheet(snapshot, &destination, &options))
+        .await
+        .map_err(|e| format!("Contact sheet failed: {}", e))?
 }
 
 #[tauri::command]
@@ -257,6 +733,7 @@ pub async fn ai_auto_enhance(
     let files = state.files.lock().unwrap();
     let file = files.get(&file_id).ok_or("File not found")?;
     let path = file.path.clone();
+    let xmp_path = filesystem::get_sidecar_path(file);
     drop(files);
 
     let current_edits = {
@@ -270,12 +747,13 @@ pub async fn ai_auto_enhance(
     let suggestion = ai_processor::analyze_image(&resized)?;
     let new_edits = ai_processor::apply_ai_suggestion(&current_edits, &suggestion, strength);
 
-    let xmp_path = filesystem::get_xmp_path(&path);
     xmp::save_xmp_file(&xmp_path, &new_edits)?;
 
     let mut states = state.edit_states.lock().unwrap();
     states.insert(file_id, new_edits.clone());
+    drop(states);
 
+    record_history(&xmp_path, &current_edits, &new_edits, "Auto enhance");
     Ok(new_edits)
 }
 
@@ -390,7 +868,7 @@ pub async fn ai_batch_enhance(
                     Ok(suggestion) => {
                         let new_edits = ai_processor::apply_ai_suggestion(&current_edits, &suggestion, strength);
 
-                        let xmp_path = filesystem::get_xmp_path(&file.path);
+                        let xmp_path = filesystem::get_sidecar_path(&file);
                         if let Err(e) = xmp::save_xmp_file(&xmp_path, &new_edits) {
                             tracing::warn!("Failed to save XMP for {}: {}", file_id, e);
                         }
@@ -399,6 +877,7 @@ pub async fn ai_batch_enhance(
                             let mut states = state.edit_states.lock().unwrap();
                             states.insert(file_id.clone(), new_edits.clone());
                         }
+                        record_history(&xmp_path, &current_edits, &new_edits, "Auto enhance");
 
                         results.push(BatchAiResult {
                             file_id: file_id.clone(),
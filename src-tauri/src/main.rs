@@ -23,9 +23,40 @@ fn main() {
             commands::get_thumbnail,
             commands::get_preview,
             commands::save_edits,
+            commands::get_history,
+            commands::undo_edits,
+            commands::redo_edits,
+            commands::jump_to_history,
+            commands::create_snapshot,
+            commands::restore_snapshot,
+            commands::delete_snapshot,
             commands::set_rating,
             commands::set_flag,
+            commands::create_virtual_copy,
+            commands::delete_virtual_copy,
+            commands::pick_white_balance,
+            commands::detect_lens,
+            commands::list_looks,
+            commands::import_look,
+            commands::delete_look,
+            commands::list_presets,
+            commands::save_preset,
+            commands::import_preset,
+            commands::delete_preset,
+            commands::apply_preset,
+            commands::copy_edits,
+            commands::paste_edits,
+            commands::sync_edits,
+            commands::list_watermarks,
+            commands::save_watermark,
+            commands::delete_watermark,
             commands::export_images,
+            commands::start_export,
+            commands::get_export_job,
+            commands::cancel_export,
+            commands::pause_export,
+            commands::resume_export,
+            commands::create_contact_sheet,
             commands::ai_analyze,
             commands::ai_auto_enhance,
             commands::ai_batch_analyze,
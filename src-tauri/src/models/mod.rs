@@ -11,6 +11,10 @@ pub struct ImageFile {
     pub modified_at: String,
     pub is_raw: bool,
     pub dimensions: Option<Dimensions>,
+    /// Set on virtual copies: another entry for `path` with its own edits,
+    /// numbered from 1. None for the file itself.
+    #[serde(default)]
+    pub virtual_copy: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +32,23 @@ pub struct CropRect {
     pub height: f32,
 }
 
+/// Region of the source image in normalized (0.0 to 1.0) coordinates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SampleRegion {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhiteBalancePick {
+    pub white_balance_temp: f32,
+    pub white_balance_tint: f32,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum Flag {
@@ -37,8 +58,339 @@ pub enum Flag {
     Reject,
 }
 
+/// How clipped sensor channels are rendered when developing RAW files.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightMode {
+    /// Clip to white; no headroom is kept.
+    Clip,
+    /// Keep headroom, fading blown areas to neutral.
+    Blend,
+    /// Keep headroom, rebuilding clipped channels from unclipped ones.
+    #[default]
+    Reconstruct,
+}
+
+/// Point on a tone curve, both axes 0 to 255 as in Lightroom.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CurvePoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Region-based curve; amounts are -100 to +100, splits 0 to 100.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ParametricCurve {
+    pub shadows: f32,
+    pub darks: f32,
+    pub lights: f32,
+    pub highlights: f32,
+    pub shadow_split: f32,
+    pub midtone_split: f32,
+    pub highlight_split: f32,
+}
+
+impl Default for ParametricCurve {
+    fn default() -> Self {
+        Self {
+            shadows: 0.0,
+            darks: 0.0,
+            lights: 0.0,
+            highlights: 0.0,
+            shadow_split: 25.0,
+            midtone_split: 50.0,
+            highlight_split: 75.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ToneCurve {
+    pub parametric: ParametricCurve,
+    pub master: Vec<CurvePoint>,
+    pub red: Vec<CurvePoint>,
+    pub green: Vec<CurvePoint>,
+    pub blue: Vec<CurvePoint>,
+}
+
+impl Default for ToneCurve {
+    fn default() -> Self {
+        let linear = vec![CurvePoint { x: 0.0, y: 0.0 }, CurvePoint { x: 255.0, y: 255.0 }];
+        Self {
+            parametric: ParametricCurve::default(),
+            master: linear.clone(),
+            red: linear.clone(),
+            green: linear.clone(),
+            blue: linear,
+        }
+    }
+}
+
+/// Color mixer amounts (-100 to +100) for the eight hue bands, ordered red,
+/// orange, yellow, green, aqua, blue, purple, magenta.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HslAdjustments {
+    pub hue: [f32; 8],
+    pub saturation: [f32; 8],
+    pub luminance: [f32; 8],
+}
+
+/// Split toning: hues in degrees, saturations 0 to 100, balance -100
+/// (favor shadows) to +100 (favor highlights).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SplitToning {
+    pub highlight_hue: f32,
+    pub highlight_saturation: f32,
+    pub shadow_hue: f32,
+    pub shadow_saturation: f32,
+    pub balance: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LutInterpolation {
+    Trilinear,
+    #[default]
+    Tetrahedral,
+}
+
+/// Creative look from a 3D LUT in the looks directory. Amount is 0 to 200
+/// percent of the LUT's effect.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Look {
+    pub name: Option<String>,
+    pub amount: f32,
+    pub interpolation: LutInterpolation,
+}
+
+impl Default for Look {
+    fn default() -> Self {
+        Self {
+            name: None,
+            amount: 100.0,
+            interpolation: LutInterpolation::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LookInfo {
+    pub name: String,
+    /// Grid points per axis.
+    pub size: u32,
+}
+
+/// One step of a file's edit history: the full edit state after a change.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub label: String,
+    pub timestamp: String,
+    pub edits: EditState,
+}
+
+/// Edit state saved under a name, kept apart from the undo stack.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub name: String,
+    pub timestamp: String,
+    pub edits: EditState,
+}
+
+/// `entries[position]` is the current state; entries after it can be redone.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EditHistory {
+    pub entries: Vec<HistoryEntry>,
+    pub position: usize,
+    pub snapshots: Vec<Snapshot>,
+}
+
+/// Selects `EditState` fields by JSON name: `fields` (every field when
+/// absent) minus `exclude`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FieldMask {
+    pub fields: Option<Vec<String>>,
+    pub exclude: Vec<String>,
+}
+
+/// A saved preset. `fields` are the `EditState` fields (JSON names) it sets;
+/// applying it leaves every other field alone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetInfo {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+/// Settings applied inside a mask. Tone and color amounts are relative,
+/// -100 to +100, except exposure which is in stops.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LocalAdjustment {
+    pub exposure: f32,
+    pub contrast: f32,
+    pub temperature: f32,
+    pub tint: f32,
+    pub saturation: f32,
+    pub clarity: f32,
+}
+
+/// Painted stroke. Points are normalized to the frame; `size` is the brush
+/// radius as a fraction of the frame's long edge.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BrushStroke {
+    pub points: Vec<[f32; 2]>,
+    pub size: f32,
+    pub feather: f32,
+    pub flow: f32,
+    #[serde(default)]
+    pub erase: bool,
+}
+
+/// Mask geometry, normalized to the frame being edited (after crop).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum MaskShape {
+    /// Full effect at `start`, fading to none at `end`.
+    #[serde(rename_all = "camelCase")]
+    Linear {
+        start_x: f32,
+        start_y: f32,
+        end_x: f32,
+        end_y: f32,
+    },
+    /// Full effect inside the ellipse, fading out over `feather` (0 to 1) of
+    /// the radius. `angle` rotates the ellipse in degrees.
+    #[serde(rename_all = "camelCase")]
+    Radial {
+        center_x: f32,
+        center_y: f32,
+        radius_x: f32,
+        radius_y: f32,
+        angle: f32,
+        feather: f32,
+    },
+    Brush { strokes: Vec<BrushStroke> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Mask {
+    pub shape: MaskShape,
+    #[serde(default)]
+    pub invert: bool,
+    #[serde(default)]
+    pub adjustment: LocalAdjustment,
+}
+
+/// Lightroom's post-crop vignette (highlight priority style). Amount is -100
+/// (darken) to +100 (lighten); midpoint, feather and highlights are 0 to 100,
+/// roundness -100 to +100.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PostCropVignette {
+    pub amount: f32,
+    pub midpoint: f32,
+    pub roundness: f32,
+    pub feather: f32,
+    /// Keeps bright areas from being darkened.
+    pub highlights: f32,
+}
+
+impl Default for PostCropVignette {
+    fn default() -> Self {
+        Self {
+            amount: 0.0,
+            midpoint: 50.0,
+            roundness: 0.0,
+            feather: 50.0,
+            highlights: 0.0,
+        }
+    }
+}
+
+/// Film grain. Amount, size and roughness are 0 to 100. A `seed` of 0 is
+/// replaced by one derived from the file name, so each photo gets its own
+/// but repeatable pattern.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Grain {
+    pub amount: f32,
+    pub size: f32,
+    pub roughness: f32,
+    pub seed: u32,
+}
+
+impl Default for Grain {
+    fn default() -> Self {
+        Self {
+            amount: 0.0,
+            size: 25.0,
+            roughness: 50.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Lens corrections. Profile data comes from the lensfun database, matched
+/// from EXIF unless `profile_name` picks a profile explicitly. Profile scales
+/// are percentages (100 applies the profile as calibrated); manual amounts
+/// are -100 to +100.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LensCorrection {
+    pub profile_enabled: bool,
+    pub profile_name: Option<String>,
+    pub distortion_scale: f32,
+    pub vignetting_scale: f32,
+    /// Positive removes barrel distortion, negative removes pincushion.
+    pub distortion: f32,
+    /// Positive brightens the corners.
+    pub vignetting: f32,
+    pub ca_red: f32,
+    pub ca_blue: f32,
+}
+
+impl Default for LensCorrection {
+    fn default() -> Self {
+        Self {
+            profile_enabled: false,
+            profile_name: None,
+            distortion_scale: 100.0,
+            vignetting_scale: 100.0,
+            distortion: 0.0,
+            vignetting: 0.0,
+            ca_red: 0.0,
+            ca_blue: 0.0,
+        }
+    }
+}
+
+/// Camera and lens details read from EXIF, with the lens profile that
+/// matched them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LensInfo {
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_model: Option<String>,
+    pub focal_length: Option<f32>,
+    pub aperture: Option<f32>,
+    pub profile_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct EditState {
     pub rating: u8,
     pub flag: Flag,
@@ -49,13 +401,34 @@ pub struct EditState {
     pub contrast: f32,
     pub highlights: f32,
     pub shadows: f32,
+    pub highlight_reconstruction: HighlightMode,
     pub white_balance_temp: f32,
     pub white_balance_tint: f32,
     pub saturation: f32,
     pub vibrance: f32,
+    pub clarity: f32,
+    pub texture: f32,
+    pub dehaze: f32,
     pub sharpening_amount: f32,
     pub sharpening_radius: f32,
+    pub sharpening_detail: f32,
+    pub sharpening_masking: f32,
     pub noise_reduction: f32,
+    pub noise_reduction_detail: f32,
+    pub color_noise_reduction: f32,
+    pub color_noise_detail: f32,
+    pub tone_curve: ToneCurve,
+    pub hsl: HslAdjustments,
+    pub monochrome: bool,
+    /// Brightness of each hue band in the gray conversion, -100 to +100, in
+    /// the same band order as `hsl`.
+    pub gray_mixer: [f32; 8],
+    pub split_toning: SplitToning,
+    pub look: Look,
+    pub masks: Vec<Mask>,
+    pub lens: LensCorrection,
+    pub vignette: PostCropVignette,
+    pub grain: Grain,
 }
 
 impl Default for EditState {
@@ -70,13 +443,32 @@ impl Default for EditState {
             contrast: 0.0,
             highlights: 0.0,
             shadows: 0.0,
+            highlight_reconstruction: HighlightMode::default(),
             white_balance_temp: 5500.0,
             white_balance_tint: 0.0,
             saturation: 0.0,
             vibrance: 0.0,
+            clarity: 0.0,
+            texture: 0.0,
+            dehaze: 0.0,
             sharpening_amount: 0.0,
             sharpening_radius: 1.0,
+            sharpening_detail: 25.0,
+            sharpening_masking: 0.0,
             noise_reduction: 0.0,
+            noise_reduction_detail: 50.0,
+            color_noise_reduction: 0.0,
+            color_noise_detail: 50.0,
+            tone_curve: ToneCurve::default(),
+            hsl: HslAdjustments::default(),
+            monochrome: false,
+            gray_mixer: [0.0; 8],
+            split_toning: SplitToning::default(),
+            look: Look::default(),
+            masks: Vec::new(),
+            lens: LensCorrection::default(),
+            vignette: PostCropVignette::default(),
+            grain: Grain::default(),
         }
     }
 }
@@ -90,13 +482,234 @@ pub struct FolderContents {
     pub thumbnail_dir: String,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Jpeg,
+    Png,
+    Tiff,
+    /// Lossless only: the pure-Rust encoder has no lossy mode.
+    Webp,
+    Avif,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Jpeg => "jpg",
+            ExportFormat::Png => "png",
+            ExportFormat::Tiff => "tif",
+            ExportFormat::Webp => "webp",
+            ExportFormat::Avif => "avif",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TiffCompression {
+    None,
+    Lzw,
+    #[default]
+    Deflate,
+    Packbits,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeMode {
+    #[default]
+    Original,
+    LongEdge,
+    ShortEdge,
+    Width,
+    Height,
+    /// Scale to fit inside `resize_width` x `resize_height`.
+    Fit,
+    /// Scale to cover `resize_width` x `resize_height`, cropping the overflow.
+    Fill,
+    Megapixels,
+    Percent,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SharpenMedium {
+    #[default]
+    Screen,
+    Matte,
+    Glossy,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SharpenAmount {
+    Low,
+    #[default]
+    Standard,
+    High,
+}
+
+/// Which source metadata is embedded in exported files.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataMode {
+    #[default]
+    All,
+    CopyrightOnly,
+    /// Everything but the GPS position, serial numbers and owner name.
+    AllExceptGps,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkAnchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    #[default]
+    BottomRight,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum WatermarkContent {
+    /// One line of text. Without `font_path` a common system font is used.
+    #[serde(rename_all = "camelCase")]
+    Text {
+        text: String,
+        #[serde(default)]
+        font_path: Option<String>,
+        color: [u8; 3],
+    },
+    /// A PNG; its alpha channel is kept.
+    #[serde(rename_all = "camelCase")]
+    Image { path: String },
+}
+
+/// A named watermark that exports refer to by name.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+pub struct Watermark {
+    pub name: String,
+    pub content: WatermarkContent,
+    #[serde(default)]
+    pub anchor: WatermarkAnchor,
+    /// Gap to the image edges, as a fraction of the short edge.
+    pub margin: f32,
+    /// Width of the watermark as a fraction of the output width.
+    pub scale: f32,
+    /// 0 (invisible) to 1.
+    pub opacity: f32,
+    /// Degrees, counter-clockwise.
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+/// What to do when an export's file name is already taken.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionPolicy {
+    Skip,
+    Overwrite,
+    /// Adds `-1`, `-2`, ... to the name.
+    #[default]
+    Unique,
+}
+
+/// Sharpening applied after resizing, tuned for where the image is shown.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OutputSharpening {
+    pub medium: SharpenMedium,
+    pub amount: SharpenAmount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct ExportOptions {
-    pub format: String,
+    pub format: ExportFormat,
+    /// 1 to 100, for JPEG and AVIF.
     pub quality: u8,
-    pub resize_mode: String,
-    pub resize_value: Option<u32>,
+    pub resize_mode: ResizeMode,
+    /// Pixels for the edge, width and height modes, megapixels or percent.
+    pub resize_value: Option<f32>,
+    /// Box for the fit and fill modes.
+    pub resize_width: Option<u32>,
+    pub resize_height: Option<u32>,
+    pub dont_enlarge: bool,
+    pub output_sharpening: Option<OutputSharpening>,
+    /// Bits per channel, 8 or 16. 16 is available for PNG and TIFF.
+    pub bit_depth: u8,
+    pub tiff_compression: TiffCompression,
+    /// AVIF encoder speed, 1 (slowest, smallest) to 10.
+    pub avif_speed: u8,
+    pub metadata: MetadataMode,
+    /// Replaces the source's copyright notice, unless metadata is `None`.
+    pub copyright: Option<String>,
+    /// File name without extension. Tokens: `{stem}`, `{folder}`, `{seq}`
+    /// (`{seq:4}` zero-pads), `{date}` (`{date:%Y%m%d}` takes a strftime
+    /// format), `{rating}`, `{text}` and `{recipe}` (the recipe name).
+    pub filename_template: String,
+    /// Path under the destination, with the same tokens; `/` separates folders.
+    pub subfolder_template: String,
+    /// Value of `{text}`.
+    pub custom_text: String,
+    /// First value of `{seq}`.
+    pub sequence_start: u32,
+    pub collision: CollisionPolicy,
+    /// JPEG only: lowers quality until the file is at most this many KB
+    /// (1024 bytes).
+    pub max_file_size_kb: Option<u32>,
+    /// With `max_file_size_kb`, makes the image smaller when the lowest
+    /// quality tried is still too large.
+    pub downscale_to_fit: bool,
+    /// Name of a watermark preset drawn on each export.
+    pub watermark: Option<String>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Jpeg,
+            quality: 90,
+            resize_mode: ResizeMode::Original,
+            resize_value: None,
+            resize_width: None,
+            resize_height: None,
+            dont_enlarge: true,
+            output_sharpening: None,
+            bit_depth: 8,
+            tiff_compression: TiffCompression::Deflate,
+            avif_speed: 6,
+            metadata: MetadataMode::All,
+            copyright: None,
+            filename_template: "{stem}".to_string(),
+            subfolder_template: String::new(),
+            custom_text: String::new(),
+            sequence_start: 1,
+            collision: CollisionPolicy::Unique,
+            max_file_size_kb: None,
+            downscale_to_fit: false,
+            watermark: None,
+        }
+    }
+}
+
+/// Named export options. One export can write several recipes per file,
+/// all derived from a single full-resolution render.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRecipe {
+    pub name: String,
+    pub options: ExportOptions,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,8 +717,151 @@ pub struct ExportOptions {
 pub struct ExportResult {
     pub success: bool,
     pub source_id: String,
+    /// Recipe the output was written for, when exporting recipes.
+    #[serde(default)]
+    pub recipe: Option<String>,
     pub destination_path: Option<String>,
     pub error: Option<String>,
+    /// Not written because the file existed and the policy is skip.
+    #[serde(default)]
+    pub skipped: bool,
+    /// JPEG quality used, which `max_file_size_kb` may have lowered.
+    #[serde(default)]
+    pub quality: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportJobState {
+    Running,
+    Paused,
+    Cancelled,
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportJobStatus {
+    pub job_id: String,
+    pub state: ExportJobState,
+    pub total: usize,
+    pub completed: usize,
+    /// Results so far, in the order the files were given.
+    pub results: Vec<ExportResult>,
+}
+
+/// Payload of the `export_progress` event, sent as each file finishes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportProgress {
+    pub job_id: String,
+    pub completed: usize,
+    pub total: usize,
+    pub result: ExportResult,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    #[default]
+    A4,
+    A3,
+    Letter,
+    Legal,
+    Tabloid,
+}
+
+impl PageSize {
+    /// Portrait width and height in millimetres.
+    pub fn millimetres(self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (210.0, 297.0),
+            PageSize::A3 => (297.0, 420.0),
+            PageSize::Letter => (215.9, 279.4),
+            PageSize::Legal => (215.9, 355.6),
+            PageSize::Tabloid => (279.4, 431.8),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ContactSheetFormat {
+    #[default]
+    Pdf,
+    /// One JPEG per page.
+    Jpeg,
+}
+
+/// A line of text under each image of a contact sheet.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionField {
+    Filename,
+    Rating,
+    /// Shutter speed, aperture, ISO and focal length.
+    Exposure,
+    Camera,
+    Date,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ContactSheetOptions {
+    pub page_size: PageSize,
+    pub landscape: bool,
+    pub columns: u32,
+    pub rows: u32,
+    /// Page margin in millimetres.
+    pub margin_mm: f32,
+    /// Gap between images in millimetres.
+    pub spacing_mm: f32,
+    pub captions: Vec<CaptionField>,
+    /// Caption text size in points.
+    pub caption_size: f32,
+    /// Printed with the page number at the foot of each page.
+    pub title: Option<String>,
+    /// Font for captions and the title; a system font when not set.
+    pub font_path: Option<String>,
+    /// Pixels per inch of the rendered pages.
+    pub dpi: u32,
+    pub format: ContactSheetFormat,
+    /// JPEG quality of the pages, also used for PDF pages.
+    pub quality: u8,
+    /// Output name without extension. JPEG pages get `-01`, `-02`, ...
+    pub file_name: String,
+}
+
+impl Default for ContactSheetOptions {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::A4,
+            landscape: false,
+            columns: 4,
+            rows: 5,
+            margin_mm: 12.0,
+            spacing_mm: 4.0,
+            captions: vec![CaptionField::Filename, CaptionField::Rating],
+            caption_size: 7.0,
+            title: None,
+            font_path: None,
+            dpi: 200,
+            format: ContactSheetFormat::Pdf,
+            quality: 90,
+            file_name: "contact-sheet".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactSheetResult {
+    /// Files written: the PDF, or one JPEG per page.
+    pub paths: Vec<String>,
+    pub pages: usize,
+    /// Files that could not be rendered. They appear on the sheet as an
+    /// empty frame with their captions.
+    pub failed: Vec<ExportResult>,
 }
 
 pub const RAW_EXTENSIONS: &[&str] = &[
@@ -126,4 +882,17 @@ impl ImageFile {
     pub fn is_raw_extension(ext: &str) -> bool {
         RAW_EXTENSIONS.contains(&ext.to_lowercase().as_str())
     }
+
+    /// File name stem for exports. Virtual copies get a `-copyN` suffix so
+    /// they do not overwrite the export of the original.
+    pub fn export_stem(&self) -> String {
+        let stem = std::path::Path::new(&self.path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.id.clone());
+        match self.virtual_copy {
+            Some(n) => format!("{}-copy{}", stem, n),
+            None => stem,
+        }
+    }
 }
@@ -0,0 +1,217 @@
+use crate::models::{HslAdjustments, SplitToning};
+
+/// Lightroom's eight color mixer bands, in hue order.
+pub const HSL_BANDS: [&str; 8] = [
+    "Red", "Orange", "Yellow", "Green", "Aqua", "Blue", "Purple", "Magenta",
+];
+
+/// Band centers in degrees.
+const BAND_CENTERS: [f32; 8] = [0.0, 30.0, 60.0, 120.0, 180.0, 240.0, 270.0, 300.0];
+
+/// Hue shift in degrees at a band's +/-100.
+const MAX_HUE_SHIFT: f32 = 30.0;
+
+/// Per-hue hue/saturation/luminance adjustments on the 0 to 255 scale used by
+/// `apply_edits`.
+pub struct ColorMixer {
+    hue: [f32; 8],
+    saturation: [f32; 8],
+    luminance: [f32; 8],
+}
+
+impl ColorMixer {
+    /// Returns `None` when every band is at zero.
+    pub fn new(hsl: &HslAdjustments) -> Option<Self> {
+        let all = hsl.hue.iter().chain(&hsl.saturation).chain(&hsl.luminance);
+        if all.clone().all(|&v| v == 0.0) {
+            return None;
+        }
+
+        Some(Self {
+            hue: hsl.hue.map(|v| v.clamp(-100.0, 100.0) / 100.0 * MAX_HUE_SHIFT),
+            saturation: hsl.saturation.map(|v| v.clamp(-100.0, 100.0) / 100.0),
+            luminance: hsl.luminance.map(|v| v.clamp(-100.0, 100.0) / 100.0),
+        })
+    }
+
+    pub fn apply(&self, r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+        let (h, s, l) = rgb_to_hsl(
+            r.clamp(0.0, 255.0) / 255.0,
+            g.clamp(0.0, 255.0) / 255.0,
+            b.clamp(0.0, 255.0) / 255.0,
+        );
+        if s <= 0.0 {
+            return (r, g, b);
+        }
+
+        let weights = band_weights(h);
+        let mut hue_shift = 0.0;
+        let mut sat_adj = 0.0;
+        let mut lum_adj = 0.0;
+        for (i, &w) in weights.iter().enumerate() {
+            if w > 0.0 {
+                hue_shift += self.hue[i] * w;
+                sat_adj += self.saturation[i] * w;
+                lum_adj += self.luminance[i] * w;
+            }
+        }
+
+        // Nearly gray pixels have no meaningful hue; fade the effect out.
+        let strength = (s * 4.0).min(1.0);
+
+        let h = (h + hue_shift * strength).rem_euclid(360.0);
+        let s = (s * (1.0 + sat_adj * strength)).clamp(0.0, 1.0);
+        let lum_adj = lum_adj * strength * 0.5;
+        let l = if lum_adj > 0.0 {
+            l + (1.0 - l) * lum_adj
+        } else {
+            l + l * lum_adj
+        };
+
+        let (r, g, b) = hsl_to_rgb(h, s, l.clamp(0.0, 1.0));
+        (r * 255.0, g * 255.0, b * 255.0)
+    }
+}
+
+/// Black and white conversion with per-hue brightness, on the 0 to 255 scale.
+pub struct GrayMixer {
+    weights: [f32; 8],
+}
+
+impl GrayMixer {
+    pub fn new(weights: &[f32; 8]) -> Self {
+        Self {
+            weights: weights.map(|v| v.clamp(-100.0, 100.0) / 100.0),
+        }
+    }
+
+    /// Gray value for a color: its luma, brightened or darkened by the bands
+    /// its hue falls in. Weak colors are affected less.
+    pub fn apply(&self, r: f32, g: f32, b: f32) -> f32 {
+        let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+        if self.weights.iter().all(|&w| w == 0.0) {
+            return luma;
+        }
+        let (h, s, _) = rgb_to_hsl(
+            r.clamp(0.0, 255.0) / 255.0,
+            g.clamp(0.0, 255.0) / 255.0,
+            b.clamp(0.0, 255.0) / 255.0,
+        );
+        if s <= 0.0 {
+            return luma;
+        }
+
+        let adjustment: f32 = band_weights(h)
+            .iter()
+            .zip(&self.weights)
+            .map(|(w, m)| w * m)
+            .sum();
+        let strength = (s * 2.0).min(1.0);
+        luma * (1.0 + adjustment * strength)
+    }
+}
+
+/// Split toning on the 0 to 255 scale: tints shadows and highlights with
+/// separate hues while keeping luma.
+pub struct SplitToner {
+    highlight: [f32; 3],
+    shadow: [f32; 3],
+    pivot: f32,
+}
+
+impl SplitToner {
+    /// Returns `None` when both saturations are zero.
+    pub fn new(toning: &SplitToning) -> Option<Self> {
+        let highlight_sat = toning.highlight_saturation.clamp(0.0, 100.0) / 100.0;
+        let shadow_sat = toning.shadow_saturation.clamp(0.0, 100.0) / 100.0;
+        if highlight_sat == 0.0 && shadow_sat == 0.0 {
+            return None;
+        }
+
+        Some(Self {
+            highlight: tint(toning.highlight_hue, highlight_sat),
+            shadow: tint(toning.shadow_hue, shadow_sat),
+            // Positive balance moves the split down so highlights cover more
+            pivot: 0.5 - toning.balance.clamp(-100.0, 100.0) / 100.0 * 0.4,
+        })
+    }
+
+    pub fn apply(&self, r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+        let l = ((0.299 * r + 0.587 * g + 0.114 * b) / 255.0).clamp(0.0, 1.0);
+        let spread = self.pivot.min(1.0 - self.pivot) * 2.0;
+        let t = ((l - self.pivot) / spread + 0.5).clamp(0.0, 1.0);
+        let highlight_weight = t * t * (3.0 - 2.0 * t);
+        // Fade out toward pure black and white
+        let extremes = (l.min(1.0 - l) * 4.0).min(1.0);
+
+        let mut out = [r, g, b];
+        for (c, v) in out.iter_mut().enumerate() {
+            let offset = self.highlight[c] * highlight_weight + self.shadow[c] * (1.0 - highlight_weight);
+            *v += offset * extremes * 255.0;
+        }
+        (out[0], out[1], out[2])
+    }
+}
+
+/// Color offset of a hue at a saturation, with zero luma so toning shifts
+/// color without brightening.
+fn tint(hue: f32, saturation: f32) -> [f32; 3] {
+    let (r, g, b) = hsl_to_rgb(hue.rem_euclid(360.0), 1.0, 0.5);
+    let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+    [r - luma, g - luma, b - luma].map(|v| v * saturation * 0.3)
+}
+
+/// Weights of each band for a hue. Adjacent bands cross-fade with a cosine
+/// ramp so weights always sum to one and there are no hard hue boundaries.
+fn band_weights(hue: f32) -> [f32; 8] {
+    let mut weights = [0.0; 8];
+    for i in 0..BAND_CENTERS.len() {
+        let start = BAND_CENTERS[i];
+        let next = (i + 1) % BAND_CENTERS.len();
+        let end = if next == 0 { 360.0 } else { BAND_CENTERS[next] };
+        if hue >= start && hue < end {
+            let t = (hue - start) / (end - start);
+            let w = 0.5 + 0.5 * (t * std::f32::consts::PI).cos();
+            weights[i] = w;
+            weights[next] = 1.0 - w;
+            break;
+        }
+    }
+    weights
+}
+
+pub fn rgb_to_hsl(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d <= f32::EPSILON {
+        return (0.0, 0.0, l);
+    }
+
+    let s = if l > 0.5 { d / (2.0 - max - min) } else { d / (max + min) };
+    let h = if max == r {
+        ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    (h * 60.0, s, l)
+}
+
+pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let hp = h / 60.0;
+    let x = c * (1.0 - (hp.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match hp as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    (r + m, g + m, b + m)
+}
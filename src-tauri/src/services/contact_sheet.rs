@@ -0,0 +1,467 @@
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use image::codecs::jpeg::{JpegEncoder, PixelDensity};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgb, RgbImage};
+use imageproc::drawing::{draw_hollow_rect_mut, draw_text_mut, text_size};
+use imageproc::rect::Rect;
+use rayon::prelude::*;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::models::{
+    CaptionField, ContactSheetFormat, ContactSheetOptions, ContactSheetResult, EditState, ExportResult, ImageFile,
+};
+use crate::services::effects::seed_for_file;
+use crate::services::exif::{self, ExifData};
+use crate::services::export;
+use crate::services::image_processor::{apply_crop, apply_edits_at_scale, resize_to_fit, rotate_image};
+use crate::services::lens::{shot_calibration, LensCorrector};
+use crate::services::naming;
+use crate::services::thumbnail::develop_image;
+use crate::services::watermark::load_font;
+
+const MM_PER_INCH: f32 = 25.4;
+const POINTS_PER_INCH: f32 = 72.0;
+
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const TEXT: Rgb<u8> = Rgb([40, 40, 40]);
+/// Frame drawn where a file could not be rendered.
+const FRAME: Rgb<u8> = Rgb([190, 190, 190]);
+
+/// Images smaller than this on either side are not worth printing.
+const MIN_IMAGE_PX: u32 = 32;
+
+/// Pixel geometry of a page.
+struct Layout {
+    /// Page size in PDF points.
+    page_points: (f32, f32),
+    page_width: u32,
+    page_height: u32,
+    margin: u32,
+    spacing: u32,
+    cell_width: u32,
+    /// Height of the image area; captions go below it.
+    image_height: u32,
+    cell_height: u32,
+    line_height: u32,
+    text_scale: PxScale,
+}
+
+impl Layout {
+    fn new(options: &ContactSheetOptions, font: &FontVec) -> Result<Self, String> {
+        let (width_mm, height_mm) = options.page_size.millimetres();
+        let (width_mm, height_mm) = if options.landscape { (height_mm, width_mm) } else { (width_mm, height_mm) };
+        let px = |mm: f32| (mm / MM_PER_INCH * options.dpi as f32).round() as i64;
+
+        let text_scale = PxScale::from(options.caption_size / POINTS_PER_INCH * options.dpi as f32);
+        let scaled = font.as_scaled(text_scale);
+        let line_height = (scaled.ascent() - scaled.descent() + scaled.line_gap()).ceil().max(1.0) as i64;
+
+        let (page_width, page_height) = (px(width_mm), px(height_mm));
+        let (margin, spacing) = (px(options.margin_mm), px(options.spacing_mm));
+        let (columns, rows) = (options.columns as i64, options.rows as i64);
+        // The foot of the page holds the title and page number
+        let footer = line_height * 2;
+        let cell_width = (page_width - 2 * margin - (columns - 1) * spacing) / columns;
+        let cell_height = (page_height - 2 * margin - footer - (rows - 1) * spacing) / rows;
+        let captions = match options.captions.len() as i64 {
+            0 => 0,
+            lines => line_height / 3 + lines * line_height,
+        };
+        let image_height = cell_height - captions;
+
+        if cell_width < MIN_IMAGE_PX as i64 || image_height < MIN_IMAGE_PX as i64 {
+            return Err("The grid does not fit on the page; use fewer columns or rows, \
+                        smaller margins or fewer captions"
+                .to_string());
+        }
+        let points = |mm: f32| mm / MM_PER_INCH * POINTS_PER_INCH;
+        Ok(Self {
+            page_points: (points(width_mm), points(height_mm)),
+            page_width: page_width as u32,
+            page_height: page_height as u32,
+            margin: margin as u32,
+            spacing: spacing as u32,
+            cell_width: cell_width as u32,
+            image_height: image_height as u32,
+            cell_height: cell_height as u32,
+            line_height: line_height as u32,
+            text_scale,
+        })
+    }
+}
+
+/// A file placed on the sheet.
+struct Entry {
+    /// Position among the files given.
+    index: usize,
+    file: ImageFile,
+    edits: EditState,
+}
+
+/// Renders the files into pages of a grid and writes them to `destination`
+/// as a PDF or a series of JPEGs. Each image goes through the same edits as
+/// an export, rendered at the size it is printed.
+pub fn create_contact_sheet(
+    snapshot: Vec<(String, Option<(ImageFile, EditState)>)>,
+    destination: &str,
+    options: &ContactSheetOptions,
+) -> Result<ContactSheetResult, String> {
+    validate_options(options)?;
+    let font = load_font(options.font_path.as_deref())?;
+    let layout = Layout::new(options, &font)?;
+
+    let mut failed = Vec::new();
+    let mut entries = Vec::new();
+    for (index, (source_id, entry)) in snapshot.into_iter().enumerate() {
+        match entry {
+            Some((file, edits)) => entries.push(Entry { index, file, edits }),
+            None => failed.push((index, failed_result(&source_id, "File not found".to_string()))),
+        }
+    }
+    if entries.is_empty() {
+        return Err("No files to include".to_string());
+    }
+
+    let per_page = (options.columns * options.rows) as usize;
+    let page_count = entries.len().div_ceil(per_page);
+    let mut pages = Vec::with_capacity(page_count);
+    for (index, chunk) in entries.chunks(per_page).enumerate() {
+        let cells: Vec<Result<RgbImage, String>> = chunk
+            .par_iter()
+            .map(|entry| render_image(entry, layout.cell_width, layout.image_height))
+            .collect();
+        for (entry, cell) in chunk.iter().zip(&cells) {
+            if let Err(e) = cell {
+                failed.push((entry.index, failed_result(&entry.file.id, e.clone())));
+            }
+        }
+        let page = draw_page(chunk, &cells, index + 1, page_count, &layout, &font, options);
+        pages.push(encode_page(&page, options)?);
+    }
+
+    fs::create_dir_all(destination).map_err(|e| format!("Create dir failed: {}", e))?;
+    let name = naming::sanitize(&options.file_name);
+    let paths = match options.format {
+        ContactSheetFormat::Pdf => {
+            let path = free_path(destination, &name, |base| vec![format!("{}.pdf", base)]).remove(0);
+            let pdf = write_pdf(&pages, &layout, options);
+            export::write_atomic(&path, &pdf)?;
+            vec![path]
+        }
+        ContactSheetFormat::Jpeg => {
+            let paths = free_path(destination, &name, |base| {
+                (1..=page_count).map(|page| format!("{}-{:02}.jpg", base, page)).collect()
+            });
+            for (path, page) in paths.iter().zip(&pages) {
+                export::write_atomic(path, page)?;
+            }
+            paths
+        }
+    };
+
+    failed.sort_by_key(|(index, _)| *index);
+    Ok(ContactSheetResult {
+        paths: paths.iter().map(|p| p.to_string_lossy().to_string()).collect(),
+        pages: page_count,
+        failed: failed.into_iter().map(|(_, result)| result).collect(),
+    })
+}
+
+fn validate_options(options: &ContactSheetOptions) -> Result<(), String> {
+    if !(1..=20).contains(&options.columns) || !(1..=20).contains(&options.rows) {
+        return Err("Columns and rows must be 1 to 20".to_string());
+    }
+    if !(72..=600).contains(&options.dpi) {
+        return Err(format!("DPI must be 72 to 600, got {}", options.dpi));
+    }
+    if !(1..=100).contains(&options.quality) {
+        return Err(format!("Quality must be 1 to 100, got {}", options.quality));
+    }
+    let lengths = [options.margin_mm, options.spacing_mm];
+    if !lengths.iter().all(|v| v.is_finite() && *v >= 0.0) {
+        return Err("Margin and spacing must not be negative".to_string());
+    }
+    if !(options.caption_size > 0.0 && options.caption_size <= 72.0) {
+        return Err("Caption size must be above 0 and at most 72 points".to_string());
+    }
+    if naming::sanitize(&options.file_name).is_empty() {
+        return Err("Contact sheet file name is empty".to_string());
+    }
+    Ok(())
+}
+
+fn failed_result(source_id: &str, error: String) -> ExportResult {
+    ExportResult {
+        success: false,
+        source_id: source_id.to_string(),
+        recipe: None,
+        destination_path: None,
+        error: Some(error),
+        skipped: false,
+        quality: None,
+    }
+}
+
+/// The first of `name`, `name-1`, `name-2`, ... whose files are all free.
+fn free_path(destination: &str, name: &str, files: impl Fn(&str) -> Vec<String>) -> Vec<PathBuf> {
+    (0..)
+        .map(|n| if n == 0 { name.to_string() } else { format!("{}-{}", name, n) })
+        .map(|base| files(&base).into_iter().map(|f| Path::new(destination).join(f)).collect::<Vec<_>>())
+        .find(|paths| paths.iter().all(|p| !p.exists()))
+        .unwrap_or_default()
+}
+
+/// The edited and cropped image, fitted into `width` by `height`. The source
+/// is developed at about that size, as for a preview.
+fn render_image(entry: &Entry, width: u32, height: u32) -> Result<RgbImage, String> {
+    let path = entry.file.path.as_str();
+    let max_size = width.max(height);
+    let (img, dimensions) = develop_image(path, entry.edits.highlight_reconstruction, Some(max_size))?;
+    let mut img = resize_to_fit(img, max_size);
+
+    // Lens geometry applies to the full frame, before crop
+    let calibration = shot_calibration(path, &entry.edits.lens);
+    if let Some(corrector) = LensCorrector::new(&entry.edits.lens, calibration.as_ref()) {
+        img = corrector.apply(img);
+    }
+
+    let img_width = img.width();
+    if let Some(ref crop) = entry.edits.crop {
+        img = apply_crop(img, crop);
+    }
+
+    let mut edits = entry.edits.clone();
+    if edits.grain.seed == 0 {
+        edits.grain.seed = seed_for_file(path);
+    }
+    // Radius-based tools are specified in source pixels
+    let scale = img_width as f32 / dimensions.width.max(1) as f32;
+    let processed = apply_edits_at_scale(img, &edits, scale);
+    let rotated = rotate_image(processed, edits.rotation);
+    Ok(rotated.resize(width, height, FilterType::Lanczos3).to_rgb8())
+}
+
+fn draw_page(
+    entries: &[Entry],
+    cells: &[Result<RgbImage, String>],
+    page: usize,
+    page_count: usize,
+    layout: &Layout,
+    font: &FontVec,
+    options: &ContactSheetOptions,
+) -> RgbImage {
+    let mut canvas = RgbImage::from_pixel(layout.page_width, layout.page_height, BACKGROUND);
+    let needs_exif = options
+        .captions
+        .iter()
+        .any(|f| matches!(f, CaptionField::Exposure | CaptionField::Camera | CaptionField::Date));
+
+    for (index, (entry, cell)) in entries.iter().zip(cells).enumerate() {
+        let column = index as u32 % options.columns;
+        let row = index as u32 / options.columns;
+        let x = layout.margin + column * (layout.cell_width + layout.spacing);
+        let y = layout.margin + row * (layout.cell_height + layout.spacing);
+
+        match cell {
+            Ok(img) => {
+                let offset_x = x + (layout.cell_width - img.width()) / 2;
+                let offset_y = y + (layout.image_height - img.height()) / 2;
+                imageops::replace(&mut canvas, img, offset_x as i64, offset_y as i64);
+            }
+            Err(_) => {
+                let frame = Rect::at(x as i32, y as i32).of_size(layout.cell_width, layout.image_height);
+                draw_hollow_rect_mut(&mut canvas, frame, FRAME);
+            }
+        }
+
+        let exif = if needs_exif { exif::read_exif(&entry.file.path) } else { None };
+        let mut line_y = y + layout.image_height + layout.line_height / 3;
+        for field in &options.captions {
+            if let Some(text) = caption(*field, entry, exif.as_ref(), font) {
+                let text = fit_text(&text, layout.cell_width, layout.text_scale, font);
+                let (text_width, _) = text_size(layout.text_scale, font, &text);
+                let text_x = x + layout.cell_width.saturating_sub(text_width) / 2;
+                draw_text_mut(&mut canvas, TEXT, text_x as i32, line_y as i32, layout.text_scale, font, &text);
+            }
+            line_y += layout.line_height;
+        }
+    }
+
+    let footer_y = (layout.page_height - layout.margin - layout.line_height) as i32;
+    let page_text = format!("{} / {}", page, page_count);
+    let (page_text_width, _) = text_size(layout.text_scale, font, &page_text);
+    let page_x = layout.page_width - layout.margin - page_text_width;
+    draw_text_mut(&mut canvas, TEXT, page_x as i32, footer_y, layout.text_scale, font, &page_text);
+    if let Some(title) = options.title.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        let width = (page_x - layout.margin).saturating_sub(layout.line_height);
+        let title = fit_text(title, width, layout.text_scale, font);
+        draw_text_mut(&mut canvas, TEXT, layout.margin as i32, footer_y, layout.text_scale, font, &title);
+    }
+    canvas
+}
+
+fn caption(field: CaptionField, entry: &Entry, exif: Option<&ExifData>, font: &FontVec) -> Option<String> {
+    match field {
+        CaptionField::Filename => Some(match entry.file.virtual_copy {
+            Some(n) => format!("{} (copy {})", entry.file.filename, n),
+            None => entry.file.filename.clone(),
+        }),
+        CaptionField::Rating => {
+            let rating = entry.edits.rating.min(5) as usize;
+            // Not every font has the star glyphs
+            Some(if font.glyph_id('★').0 != 0 && font.glyph_id('☆').0 != 0 {
+                format!("{}{}", "★".repeat(rating), "☆".repeat(5 - rating))
+            } else {
+                format!("Rating {}/5", rating)
+            })
+        }
+        CaptionField::Exposure => {
+            let exif = exif?;
+            let parts: Vec<String> = [
+                exif.shutter_speed().map(|s| format!("{} s", s.trim_end_matches('s'))),
+                exif.f_number().map(|f| format!("f/{}", (f * 10.0).round() / 10.0)),
+                exif.iso().map(|iso| format!("ISO {}", iso)),
+                exif.focal_length().map(|mm| format!("{} mm", mm.round())),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            (!parts.is_empty()).then(|| parts.join("  "))
+        }
+        CaptionField::Camera => {
+            let exif = exif?;
+            match (exif.make(), exif.model()) {
+                // Models often repeat the make ("Canon" / "Canon EOS R5")
+                (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+                (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+                (make, model) => make.or(model),
+            }
+        }
+        CaptionField::Date => exif?
+            .date_taken()
+            .and_then(|d| chrono::NaiveDateTime::parse_from_str(&d, "%Y-%m-%dT%H:%M:%S").ok())
+            .map(|d| d.format("%Y-%m-%d %H:%M").to_string()),
+    }
+}
+
+/// Shortens text with an ellipsis until it fits in `width` pixels.
+fn fit_text(text: &str, width: u32, scale: PxScale, font: &FontVec) -> String {
+    if text_size(scale, font, text).0 <= width {
+        return text.to_string();
+    }
+    let mut chars: Vec<char> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let candidate = format!("{}…", chars.iter().collect::<String>().trim_end());
+        if text_size(scale, font, &candidate).0 <= width {
+            return candidate;
+        }
+    }
+    String::new()
+}
+
+fn encode_page(page: &RgbImage, options: &ContactSheetOptions) -> Result<Vec<u8>, String> {
+    let mut buffer = Cursor::new(Vec::new());
+    let mut encoder = JpegEncoder::new_with_quality(&mut buffer, options.quality);
+    // So the JPEG pages print at the page size
+    encoder.set_pixel_density(PixelDensity::dpi(options.dpi.min(u16::MAX as u32) as u16));
+    DynamicImage::ImageRgb8(page.clone())
+        .write_with_encoder(encoder)
+        .map_err(|e| format!("Encode failed: {}", e))?;
+    Ok(buffer.into_inner())
+}
+
+/// Objects of a PDF being written, with their byte offsets for the
+/// cross-reference table.
+#[derive(Default)]
+struct PdfWriter {
+    data: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    /// Appends the next object; objects are numbered from 1 in order.
+    fn object(&mut self, body: &[u8]) {
+        self.offsets.push(self.data.len());
+        self.data.extend_from_slice(format!("{} 0 obj\n", self.offsets.len()).as_bytes());
+        self.data.extend_from_slice(body);
+        self.data.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn stream(&mut self, dictionary: &str, content: &[u8]) {
+        let mut body = format!("<< {}/Length {} >>\nstream\n", dictionary, content.len()).into_bytes();
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\nendstream");
+        self.object(&body);
+    }
+
+    fn finish(mut self, root: usize, info: usize) -> Vec<u8> {
+        let xref = self.data.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            table.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            root,
+            info,
+            xref
+        ));
+        self.data.extend_from_slice(table.as_bytes());
+        self.data
+    }
+}
+
+/// A PDF with each page's JPEG drawn over the whole page.
+fn write_pdf(pages: &[Vec<u8>], layout: &Layout, options: &ContactSheetOptions) -> Vec<u8> {
+    let mut pdf = PdfWriter::default();
+    pdf.data.extend_from_slice(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n");
+
+    // Catalog, page tree and info first; then page, contents and image for
+    // each page
+    let page_id = |index: usize| 4 + index * 3;
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", page_id(i))).collect();
+    pdf.object(b"<< /Type /Catalog /Pages 2 0 R >>");
+    pdf.object(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()).as_bytes());
+    let mut info = String::from("<< /Producer (PhotoCull)");
+    if let Some(title) = options.title.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        info.push_str(&format!(" /Title {}", pdf_text(title)));
+    }
+    info.push_str(" >>");
+    pdf.object(info.as_bytes());
+
+    let (width, height) = layout.page_points;
+    for (index, jpeg) in pages.iter().enumerate() {
+        let id = page_id(index);
+        pdf.object(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+                 /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                width,
+                height,
+                id + 2,
+                id + 1
+            )
+            .as_bytes(),
+        );
+        pdf.stream("", format!("q {:.2} 0 0 {:.2} 0 0 cm /Im0 Do Q", width, height).as_bytes());
+        pdf.stream(
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+                 /BitsPerComponent 8 /Filter /DCTDecode ",
+                layout.page_width, layout.page_height
+            ),
+            jpeg,
+        );
+    }
+    pdf.finish(1, 3)
+}
+
+/// A PDF text string in UTF-16, which takes any character.
+fn pdf_text(text: &str) -> String {
+    let hex: String = text.encode_utf16().map(|unit| format!("{:04X}", unit)).collect();
+    format!("<FEFF{}>", hex)
+}
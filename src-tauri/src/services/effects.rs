@@ -0,0 +1,140 @@
+use rayon::prelude::*;
+
+use crate::models::{Grain, PostCropVignette};
+use crate::services::filters::gaussian_blur;
+use crate::services::white_balance::{linear_to_srgb, srgb_to_linear};
+
+/// Applies the post-crop vignette in linear light. Darkening spares bright
+/// pixels in proportion to `highlights`, like Lightroom's highlight priority.
+pub fn apply_vignette(pixels: &mut [[f32; 3]], width: u32, height: u32, vignette: &PostCropVignette) {
+    let amount = vignette.amount.clamp(-100.0, 100.0) / 100.0;
+    let (w, h) = (width as usize, height as usize);
+    if amount == 0.0 || w == 0 || h == 0 {
+        return;
+    }
+
+    // Roundness above zero moves the ellipse toward a circle; below zero it
+    // squares the shape off toward the frame.
+    let roundness = vignette.roundness.clamp(-100.0, 100.0) / 100.0;
+    let radius = (w.min(h) as f32 / 2.0).max(1.0);
+    let t = roundness.max(0.0);
+    let ax = w as f32 / 2.0 + (radius - w as f32 / 2.0) * t;
+    let ay = h as f32 / 2.0 + (radius - h as f32 / 2.0) * t;
+    let exponent = 2.0 + (-roundness).max(0.0) * 6.0;
+
+    let midpoint = 0.5 + 0.8 * vignette.midpoint.clamp(0.0, 100.0) / 100.0;
+    let width_of_ramp = 0.05 + 0.9 * vignette.feather.clamp(0.0, 100.0) / 100.0;
+    let start = midpoint - width_of_ramp / 2.0;
+    let protect = vignette.highlights.clamp(0.0, 100.0) / 100.0;
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+
+    pixels.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        let v = ((y as f32 + 0.5 - cy) / ay).abs();
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let u = ((x as f32 + 0.5 - cx) / ax).abs();
+            let d = (u.powf(exponent) + v.powf(exponent)).powf(1.0 / exponent);
+            let effect = smoothstep((d - start) / width_of_ramp);
+            if effect <= 0.0 {
+                continue;
+            }
+
+            let lin = pixel.map(|c| srgb_to_linear(c / 255.0));
+            let out = if amount < 0.0 {
+                let lum = 0.2126 * lin[0] + 0.7152 * lin[1] + 0.0722 * lin[2];
+                let spared = protect * smoothstep((lum - 0.25) / 0.75);
+                let factor = 1.0 + amount * effect * (1.0 - spared);
+                lin.map(|c| c * factor)
+            } else {
+                lin.map(|c| c + (1.0 - c) * amount * effect)
+            };
+            *pixel = out.map(|c| linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0);
+        }
+    });
+}
+
+/// Adds monochrome film grain. `size` is in source pixels, so `scale` (the
+/// render's fraction of the source resolution) keeps previews and exports
+/// alike; grain finer than a preview pixel is faded rather than aliased.
+pub fn apply_grain(pixels: &mut [[f32; 3]], width: u32, height: u32, grain: &Grain, scale: f32) {
+    let amount = grain.amount.clamp(0.0, 100.0) / 100.0;
+    let (w, h) = (width as usize, height as usize);
+    if amount == 0.0 || w < 2 || h < 2 {
+        return;
+    }
+
+    let sigma = (0.4 + grain.size.clamp(0.0, 100.0) / 100.0 * 2.6) * scale;
+    let visible = (sigma / 0.5).min(1.0);
+    let sigma = sigma.max(0.5);
+    let roughness = grain.roughness.clamp(0.0, 100.0) / 100.0;
+
+    // Roughness mixes in a coarser octave, making the grain clumpier
+    let fine = noise_plane(w, h, grain.seed, sigma);
+    let coarse = noise_plane(w, h, grain.seed ^ 0x9E37_79B9, sigma * 2.5);
+    let mut noise: Vec<f32> = fine
+        .par_iter()
+        .zip(&coarse)
+        .map(|(f, c)| f * (1.0 - roughness) + c * roughness)
+        .collect();
+    normalize(&mut noise);
+
+    let strength = amount * 0.12 * 255.0 * visible;
+    pixels.par_iter_mut().zip(noise.par_iter()).for_each(|(pixel, n)| {
+        let lum = (0.299 * pixel[0] + 0.587 * pixel[1] + 0.114 * pixel[2]) / 255.0;
+        // Grain shows most in the midtones, as on film
+        let weight = 0.3 + 0.7 * 4.0 * lum * (1.0 - lum);
+        let delta = n * strength * weight;
+        for c in pixel.iter_mut() {
+            *c = (*c + delta).clamp(0.0, 255.0);
+        }
+    });
+}
+
+/// Grain seed derived from a file name (FNV-1a), stable across folders and
+/// machines. Never 0, which means "derive one".
+pub fn seed_for_file(path: &str) -> u32 {
+    let name = std::path::Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let hash = name
+        .bytes()
+        .fold(0x811C_9DC5u32, |h, b| (h ^ b as u32).wrapping_mul(0x0100_0193));
+    hash.max(1)
+}
+
+/// Blurred white noise with unit standard deviation.
+fn noise_plane(w: usize, h: usize, seed: u32, sigma: f32) -> Vec<f32> {
+    let white: Vec<f32> = (0..w * h)
+        .into_par_iter()
+        .map(|i| gaussian_noise(seed, i as u64))
+        .collect();
+    let mut blurred = gaussian_blur(&white, w, h, sigma);
+    normalize(&mut blurred);
+    blurred
+}
+
+fn normalize(plane: &mut [f32]) {
+    let n = plane.len() as f32;
+    let mean = plane.iter().sum::<f32>() / n;
+    let std = (plane.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / n).sqrt();
+    if std > 1e-6 {
+        plane.par_iter_mut().for_each(|v| *v = (*v - mean) / std);
+    }
+}
+
+/// Approximately normal noise from a hash of seed and index: the sum of four
+/// uniform 16-bit values (Irwin-Hall), scaled to unit variance.
+fn gaussian_noise(seed: u32, index: u64) -> f32 {
+    let mut z = index ^ ((seed as u64) << 32);
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    let sum: f32 = (0..4).map(|i| ((z >> (i * 16)) & 0xFFFF) as f32 / 65535.0).sum();
+    (sum - 2.0) * 3.0f32.sqrt()
+}
+
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
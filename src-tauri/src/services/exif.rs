@@ -0,0 +1,408 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// EXIF lives near the start of JPEGs and TIFF-based RAW files; this is
+/// enough to reach it without reading whole RAW files.
+const MAX_HEADER_BYTES: u64 = 4 * 1024 * 1024;
+
+pub const TAG_IMAGE_DESCRIPTION: u16 = 0x010E;
+pub const TAG_MAKE: u16 = 0x010F;
+pub const TAG_MODEL: u16 = 0x0110;
+pub const TAG_ORIENTATION: u16 = 0x0112;
+pub const TAG_X_RESOLUTION: u16 = 0x011A;
+pub const TAG_Y_RESOLUTION: u16 = 0x011B;
+pub const TAG_RESOLUTION_UNIT: u16 = 0x0128;
+pub const TAG_SOFTWARE: u16 = 0x0131;
+pub const TAG_DATE_TIME: u16 = 0x0132;
+pub const TAG_ARTIST: u16 = 0x013B;
+pub const TAG_XMP: u16 = 0x02BC;
+pub const TAG_COPYRIGHT: u16 = 0x8298;
+pub const TAG_EXPOSURE_TIME: u16 = 0x829A;
+pub const TAG_F_NUMBER: u16 = 0x829D;
+pub const TAG_EXIF_IFD: u16 = 0x8769;
+pub const TAG_GPS_IFD: u16 = 0x8825;
+pub const TAG_ISO: u16 = 0x8827;
+pub const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+pub const TAG_FOCAL_LENGTH: u16 = 0x920A;
+pub const TAG_MAKER_NOTE: u16 = 0x927C;
+pub const TAG_PIXEL_X_DIMENSION: u16 = 0xA002;
+pub const TAG_PIXEL_Y_DIMENSION: u16 = 0xA003;
+pub const TAG_INTEROP_IFD: u16 = 0xA005;
+pub const TAG_CAMERA_OWNER: u16 = 0xA430;
+pub const TAG_BODY_SERIAL: u16 = 0xA431;
+pub const TAG_LENS_MAKE: u16 = 0xA433;
+pub const TAG_LENS_MODEL: u16 = 0xA434;
+pub const TAG_LENS_SERIAL: u16 = 0xA435;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ifd {
+    Primary,
+    Exif,
+    Gps,
+}
+
+/// A single tag with its value bytes in the source byte order.
+#[derive(Debug, Clone)]
+pub struct ExifEntry {
+    pub ifd: Ifd,
+    pub tag: u16,
+    pub format: u16,
+    pub count: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExifData {
+    pub little_endian: bool,
+    pub entries: Vec<ExifEntry>,
+}
+
+/// Reads EXIF from a JPEG or TIFF-based (most RAW) file.
+pub fn read_exif(path: &str) -> Option<ExifData> {
+    let mut data = Vec::new();
+    File::open(Path::new(path))
+        .ok()?
+        .take(MAX_HEADER_BYTES)
+        .read_to_end(&mut data)
+        .ok()?;
+    parse_exif(&data)
+}
+
+pub fn parse_exif(data: &[u8]) -> Option<ExifData> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        find_jpeg_exif(data).and_then(parse_tiff)
+    } else {
+        parse_tiff(data)
+    }
+}
+
+fn find_jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        // Start of scan: no more metadata segments follow
+        if marker == 0xDA {
+            return None;
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return Some(&segment[6..]);
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+/// Parses a TIFF structure, following the EXIF and GPS sub-IFDs.
+pub fn parse_tiff(data: &[u8]) -> Option<ExifData> {
+    let little_endian = match data.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let reader = TiffReader { data, little_endian };
+    // 42 for TIFF/DNG/CR2/NEF/ARW; ORF and RW2 use their own magic numbers
+    if !matches!(reader.u16(2)?, 42 | 0x4F52 | 0x5352 | 0x55) {
+        return None;
+    }
+
+    let mut exif = ExifData { little_endian, entries: Vec::new() };
+    let ifd0 = reader.u32(4)? as usize;
+    reader.read_ifd(ifd0, Ifd::Primary, &mut exif.entries, 0);
+    Some(exif)
+}
+
+struct TiffReader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl TiffReader<'_> {
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let b: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let b: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    fn read_ifd(&self, offset: usize, ifd: Ifd, entries: &mut Vec<ExifEntry>, depth: u8) {
+        if depth > 2 {
+            return;
+        }
+        let Some(count) = self.u16(offset) else {
+            return;
+        };
+
+        for i in 0..count as usize {
+            let entry = offset + 2 + i * 12;
+            let (Some(tag), Some(format), Some(n)) =
+                (self.u16(entry), self.u16(entry + 2), self.u32(entry + 4))
+            else {
+                return;
+            };
+
+            if tag == TAG_EXIF_IFD || tag == TAG_GPS_IFD {
+                if let Some(sub) = self.u32(entry + 8) {
+                    let kind = if tag == TAG_EXIF_IFD { Ifd::Exif } else { Ifd::Gps };
+                    self.read_ifd(sub as usize, kind, entries, depth + 1);
+                }
+                continue;
+            }
+            if tag == TAG_INTEROP_IFD {
+                continue;
+            }
+
+            let Some(size) = format_size(format).and_then(|s| s.checked_mul(n as usize)) else {
+                continue;
+            };
+            let start = if size <= 4 {
+                entry + 8
+            } else {
+                match self.u32(entry + 8) {
+                    Some(o) => o as usize,
+                    None => continue,
+                }
+            };
+            if let Some(bytes) = self.data.get(start..start.saturating_add(size)) {
+                entries.push(ExifEntry { ifd, tag, format, count: n, data: bytes.to_vec() });
+            }
+        }
+    }
+}
+
+/// Bytes an IFD takes including its out-of-line values, with room for
+/// `extra` pointer entries.
+fn ifd_size(entries: &[&ExifEntry], extra: usize) -> usize {
+    let values: usize = entries
+        .iter()
+        .filter(|e| e.data.len() > 4)
+        .map(|e| e.data.len() + e.data.len() % 2)
+        .sum();
+    2 + (entries.len() + extra) * 12 + 4 + values
+}
+
+pub fn format_size(format: u16) -> Option<usize> {
+    match format {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+impl ExifData {
+    pub fn get(&self, ifd: Ifd, tag: u16) -> Option<&ExifEntry> {
+        self.entries.iter().find(|e| e.ifd == ifd && e.tag == tag)
+    }
+
+    pub fn string(&self, ifd: Ifd, tag: u16) -> Option<String> {
+        let entry = self.get(ifd, tag)?;
+        if entry.format != 2 && entry.format != 7 {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&entry.data);
+        let trimmed = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        (!trimmed.is_empty()).then(|| trimmed.to_string())
+    }
+
+    pub fn uint(&self, ifd: Ifd, tag: u16) -> Option<u32> {
+        let entry = self.get(ifd, tag)?;
+        match entry.format {
+            // Entries with a count of 0 have no data
+            3 => Some(self.read_u16(entry.data.get(..2)?) as u32),
+            4 => Some(self.read_u32(entry.data.get(..4)?)),
+            _ => None,
+        }
+    }
+
+    pub fn rational(&self, ifd: Ifd, tag: u16) -> Option<f32> {
+        let entry = self.get(ifd, tag)?;
+        if entry.format != 5 && entry.format != 10 {
+            return self.uint(ifd, tag).map(|v| v as f32);
+        }
+        let num = self.read_u32(entry.data.get(0..4)?);
+        let den = self.read_u32(entry.data.get(4..8)?);
+        if den == 0 {
+            return None;
+        }
+        Some(if entry.format == 10 {
+            num as i32 as f32 / den as i32 as f32
+        } else {
+            num as f32 / den as f32
+        })
+    }
+
+    fn read_u16(&self, b: &[u8]) -> u16 {
+        let b = [b[0], b[1]];
+        if self.little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) }
+    }
+
+    fn read_u32(&self, b: &[u8]) -> u32 {
+        let b = [b[0], b[1], b[2], b[3]];
+        if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) }
+    }
+
+    /// Adds or replaces an ASCII tag.
+    pub fn set_string(&mut self, ifd: Ifd, tag: u16, value: &str) {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        self.set(ExifEntry { ifd, tag, format: 2, count: data.len() as u32, data });
+    }
+
+    /// Adds or replaces a SHORT (or LONG, if it does not fit) tag.
+    pub fn set_uint(&mut self, ifd: Ifd, tag: u16, value: u32) {
+        let (format, data) = match u16::try_from(value) {
+            Ok(v) if self.little_endian => (3, v.to_le_bytes().to_vec()),
+            Ok(v) => (3, v.to_be_bytes().to_vec()),
+            Err(_) if self.little_endian => (4, value.to_le_bytes().to_vec()),
+            Err(_) => (4, value.to_be_bytes().to_vec()),
+        };
+        self.set(ExifEntry { ifd, tag, format, count: 1, data });
+    }
+
+    fn set(&mut self, entry: ExifEntry) {
+        match self.entries.iter_mut().find(|e| e.ifd == entry.ifd && e.tag == entry.tag) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Serializes the entries as a TIFF structure in the source byte order,
+    /// as embedded in JPEG APP1 and PNG eXIf. Values are copied verbatim, so
+    /// tags whose data holds offsets (MakerNote, thumbnails) must be removed
+    /// first.
+    pub fn to_tiff(&self) -> Vec<u8> {
+        let sorted = |ifd: Ifd| {
+            let mut entries: Vec<&ExifEntry> = self.entries.iter().filter(|e| e.ifd == ifd).collect();
+            entries.sort_by_key(|e| e.tag);
+            entries
+        };
+        let (primary, exif, gps) = (sorted(Ifd::Primary), sorted(Ifd::Exif), sorted(Ifd::Gps));
+
+        // Sub-IFDs follow IFD0 and its data; IFD0 gains one pointer per sub-IFD
+        let pointers = !exif.is_empty() as usize + !gps.is_empty() as usize;
+        let exif_offset = 8 + ifd_size(&primary, pointers);
+        let gps_offset = exif_offset + if exif.is_empty() { 0 } else { ifd_size(&exif, 0) };
+
+        let mut out = Vec::new();
+        out.extend_from_slice(if self.little_endian { b"II" } else { b"MM" });
+        out.extend_from_slice(&self.u16_bytes(42));
+        out.extend_from_slice(&self.u32_bytes(8));
+
+        let mut ifd0: Vec<(u16, u16, u32, Vec<u8>)> =
+            primary.iter().map(|e| (e.tag, e.format, e.count, e.data.clone())).collect();
+        if !exif.is_empty() {
+            ifd0.push((TAG_EXIF_IFD, 4, 1, self.u32_bytes(exif_offset as u32).to_vec()));
+        }
+        if !gps.is_empty() {
+            ifd0.push((TAG_GPS_IFD, 4, 1, self.u32_bytes(gps_offset as u32).to_vec()));
+        }
+        ifd0.sort_by_key(|e| e.0);
+        self.write_ifd(&mut out, &ifd0);
+
+        for entries in [exif, gps] {
+            if !entries.is_empty() {
+                let entries: Vec<_> = entries.iter().map(|e| (e.tag, e.format, e.count, e.data.clone())).collect();
+                self.write_ifd(&mut out, &entries);
+            }
+        }
+        out
+    }
+
+    /// Writes an IFD at the end of `out`, followed by values too large to
+    /// fit in their entries.
+    fn write_ifd(&self, out: &mut Vec<u8>, entries: &[(u16, u16, u32, Vec<u8>)]) {
+        let start = out.len();
+        let mut data_offset = start + 2 + entries.len() * 12 + 4;
+        let mut data = Vec::new();
+
+        out.extend_from_slice(&self.u16_bytes(entries.len() as u16));
+        for (tag, format, count, value) in entries {
+            out.extend_from_slice(&self.u16_bytes(*tag));
+            out.extend_from_slice(&self.u16_bytes(*format));
+            out.extend_from_slice(&self.u32_bytes(*count));
+            if value.len() <= 4 {
+                let mut inline = value.clone();
+                inline.resize(4, 0);
+                out.extend_from_slice(&inline);
+            } else {
+                out.extend_from_slice(&self.u32_bytes(data_offset as u32));
+                data.extend_from_slice(value);
+                if value.len() % 2 == 1 {
+                    data.push(0);
+                }
+                data_offset = start + 2 + entries.len() * 12 + 4 + data.len();
+            }
+        }
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&data);
+    }
+
+    fn u16_bytes(&self, value: u16) -> [u8; 2] {
+        if self.little_endian { value.to_le_bytes() } else { value.to_be_bytes() }
+    }
+
+    fn u32_bytes(&self, value: u32) -> [u8; 4] {
+        if self.little_endian { value.to_le_bytes() } else { value.to_be_bytes() }
+    }
+
+    pub fn make(&self) -> Option<String> {
+        self.string(Ifd::Primary, TAG_MAKE)
+    }
+
+    pub fn model(&self) -> Option<String> {
+        self.string(Ifd::Primary, TAG_MODEL)
+    }
+
+    pub fn lens_model(&self) -> Option<String> {
+        self.string(Ifd::Exif, TAG_LENS_MODEL)
+    }
+
+    pub fn focal_length(&self) -> Option<f32> {
+        self.rational(Ifd::Exif, TAG_FOCAL_LENGTH)
+    }
+
+    pub fn f_number(&self) -> Option<f32> {
+        self.rational(Ifd::Exif, TAG_F_NUMBER)
+    }
+
+    pub fn iso(&self) -> Option<u32> {
+        self.uint(Ifd::Exif, TAG_ISO)
+    }
+
+    pub fn orientation(&self) -> Option<u16> {
+        self.uint(Ifd::Primary, TAG_ORIENTATION).map(|v| v as u16)
+    }
+
+    /// Shutter speed formatted as photographers write it ("1/250", "2s").
+    pub fn shutter_speed(&self) -> Option<String> {
+        let t = self.rational(Ifd::Exif, TAG_EXPOSURE_TIME)?;
+        if t <= 0.0 {
+            return None;
+        }
+        Some(if t < 1.0 {
+            format!("1/{}", (1.0 / t).round())
+        } else {
+            format!("{}s", t)
+        })
+    }
+
+    /// Capture time as an ISO 8601 local timestamp.
+    pub fn date_taken(&self) -> Option<String> {
+        let raw = self
+            .string(Ifd::Exif, TAG_DATE_TIME_ORIGINAL)
+            .or_else(|| self.string(Ifd::Primary, TAG_DATE_TIME))?;
+        chrono::NaiveDateTime::parse_from_str(&raw, "%Y:%m:%d %H:%M:%S")
+            .ok()
+            .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string())
+    }
+}
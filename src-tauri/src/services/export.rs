@@ -1,85 +1,557 @@
+use image::codecs::avif::AvifEncoder;
 use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageEncoder};
+use std::collections::HashSet;
 use std::fs;
 use std::io::Cursor;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use tiff::encoder::compression::DeflateLevel;
+use tiff::encoder::{colortype, Compression, DirectoryEncoder, TiffEncoder};
+use tiff::tags::{Predictor, Tag};
+use tiff::TiffResult;
 
-use crate::models::{EditState, ExportOptions, ExportResult};
-use crate::services::image_processor::{apply_edits, resize_to_fit, rotate_image};
-use crate::services::thumbnail::load_image;
+use crate::models::{
+    CollisionPolicy, EditState, ExportFormat, ExportOptions, ExportRecipe, ExportResult, ImageFile, ResizeMode,
+    TiffCompression,
+};
+use crate::services::effects::seed_for_file;
+use crate::services::exif::{Ifd, TAG_XMP};
+use crate::services::filesystem::get_sidecar_path;
+use crate::services::image_processor::{apply_edits, apply_edits_16, apply_output_sharpening, rotate_image};
+use crate::services::lens::{shot_calibration, LensCorrector};
+use crate::services::metadata::{self, ExportMetadata};
+use crate::services::naming::{self, NameContext};
+use crate::services::thumbnail::develop_image;
+use crate::services::watermark;
 
-pub fn export_image(
-    image_path: &str,
-    file_id: &str,
+/// Where an export goes, decided before any image is processed.
+pub enum Destination {
+    Write(PathBuf),
+    /// The file exists and the collision policy is skip.
+    Skip(PathBuf),
+}
+
+/// Where one file's outputs go, one per recipe.
+pub type FileDestinations = Vec<Result<Destination, String>>;
+
+/// Works out the output paths of each file, one per recipe, in export
+/// order. Names claimed earlier in the same export count as taken, so two
+/// outputs never write the same file, even with the overwrite policy.
+pub fn plan_destinations(
+    files: &[(&ImageFile, &EditState)],
     destination: &str,
-    edits: &EditState,
-    options: &ExportOptions,
-) -> ExportResult {
-    let source_path = Path::new(image_path);
-    let filename = source_path
-        .file_stem()
-        .map(|s| s.to_string_lossy().to_string())
-        .unwrap_or_else(|| file_id.to_string());
-
-    let ext = match options.format.as_str() {
-        "png" => "png",
-        _ => "jpg",
-    };
-    let dest_path = Path::new(destination).join(format!("{}.{}", filename, ext));
+    recipes: &[ExportRecipe],
+) -> Vec<FileDestinations> {
+    // Compared case-insensitively, as on macOS and Windows
+    let mut taken: HashSet<String> = HashSet::new();
+    let mut claim = |path: &Path| taken.insert(path.to_string_lossy().to_lowercase());
+
+    files
+        .iter()
+        .enumerate()
+        .map(|(index, (file, edits))| {
+            recipes
+                .iter()
+                .map(|recipe| {
+                    let options = &recipe.options;
+                    let context = NameContext {
+                        file,
+                        sequence: options.sequence_start.saturating_add(index as u32),
+                        rating: edits.rating,
+                        custom_text: &options.custom_text,
+                        recipe: &recipe.name,
+                    };
+                    let relative = naming::relative_path(
+                        &options.filename_template,
+                        &options.subfolder_template,
+                        options.format.extension(),
+                        &context,
+                    )?;
+                    let path = Path::new(destination).join(relative);
+
+                    let free = !path.exists() && claim(&path);
+                    match options.collision {
+                        _ if free => return Ok(Destination::Write(path)),
+                        CollisionPolicy::Skip => return Ok(Destination::Skip(path)),
+                        // Replaces a file on disk, but not one written by this export
+                        CollisionPolicy::Overwrite if claim(&path) => return Ok(Destination::Write(path)),
+                        _ => {}
+                    }
+                    (1..)
+                        .map(|n| numbered(&path, n))
+                        .find(|candidate| !candidate.exists() && claim(candidate))
+                        .map(Destination::Write)
+                        .ok_or_else(|| "No free file name".to_string())
+                })
+                .collect()
+        })
+        .collect()
+}
 
-    match do_export(image_path, &dest_path, edits, options) {
-        Ok(_) => ExportResult {
-            success: true,
-            source_id: file_id.to_string(),
-            destination_path: Some(dest_path.to_string_lossy().to_string()),
-            error: None,
-        },
-        Err(e) => ExportResult {
-            success: false,
-            source_id: file_id.to_string(),
-            destination_path: None,
-            error: Some(e),
-        },
+/// `name.ext` as `name-n.ext`.
+fn numbered(path: &Path, n: u32) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}-{}.{}", stem, n, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}-{}", stem, n)),
     }
 }
 
-fn do_export(
-    image_path: &str,
-    dest_path: &Path,
+/// The recipe name reported in results; `None` for a plain options export.
+fn recipe_name(recipe: &ExportRecipe) -> Option<String> {
+    (!recipe.name.is_empty()).then(|| recipe.name.clone())
+}
+
+pub fn failed_result(source_id: &str, recipe: &ExportRecipe, error: String) -> ExportResult {
+    ExportResult {
+        success: false,
+        source_id: source_id.to_string(),
+        recipe: recipe_name(recipe),
+        destination_path: None,
+        error: Some(error),
+        skipped: false,
+        quality: None,
+    }
+}
+
+/// Exports one file for every recipe. The file is developed once at full
+/// resolution and each output is derived from that image; it is not
+/// developed at all when every output is skipped. Results are in recipe
+/// order.
+pub fn export_image(
+    file: &ImageFile,
+    destinations: &[Result<Destination, String>],
     edits: &EditState,
-    options: &ExportOptions,
-) -> Result<(), String> {
-    let img = load_image(image_path)?;
+    recipes: &[ExportRecipe],
+) -> Vec<ExportResult> {
+    let image_path = file.path.as_str();
+    let file_id = file.id.as_str();
+    let sidecar_path = get_sidecar_path(file);
+
+    let written: Vec<&ExportRecipe> = destinations
+        .iter()
+        .zip(recipes)
+        .filter(|(d, _)| matches!(d, Ok(Destination::Write(_))))
+        .map(|(_, recipe)| recipe)
+        .collect();
+    // One 16-bit output means developing at 16 bits; 8-bit outputs round it
+    let high_depth = written.iter().any(|r| r.options.bit_depth == 16);
+    let developed = (!written.is_empty()).then(|| develop(image_path, edits, high_depth));
+
+    destinations
+        .iter()
+        .zip(recipes)
+        .map(|(destination, recipe)| {
+            let (dest_path, skipped) = match destination {
+                Ok(Destination::Write(path)) => (path, false),
+                Ok(Destination::Skip(path)) => (path, true),
+                Err(e) => return failed_result(file_id, recipe, e.clone()),
+            };
+            let quality = if skipped {
+                None
+            } else {
+                let written = match &developed {
+                    Some(Ok(img)) => {
+                        write_output(img.clone(), image_path, &sidecar_path, dest_path, edits.rating, &recipe.options)
+                    }
+                    Some(Err(e)) => Err(e.clone()),
+                    None => unreachable!("the file is developed when an output is written"),
+                };
+                match written {
+                    Ok(quality) => quality,
+                    Err(e) => return failed_result(file_id, recipe, e),
+                }
+            };
+            ExportResult {
+                success: true,
+                source_id: file_id.to_string(),
+                recipe: recipe_name(recipe),
+                destination_path: Some(dest_path.to_string_lossy().to_string()),
+                error: None,
+                skipped,
+                quality,
+            }
+        })
+        .collect()
+}
+
+/// The full-resolution image with the edits applied, the part of an export
+/// shared by all recipes. `high_depth` keeps 16 bits per channel.
+fn develop(image_path: &str, edits: &EditState, high_depth: bool) -> Result<DynamicImage, String> {
+    let (mut img, _) = develop_image(image_path, edits.highlight_reconstruction, None)?;
+
+    let calibration = shot_calibration(image_path, &edits.lens);
+    if let Some(corrector) = LensCorrector::new(&edits.lens, calibration.as_ref()) {
+        img = corrector.apply(img);
+    }
+
+    let mut edits = edits.clone();
+    if edits.grain.seed == 0 {
+        edits.grain.seed = seed_for_file(image_path);
+    }
+    let edits = &edits;
 
-    let mut processed = apply_edits(img, edits);
+    let mut processed = if high_depth { apply_edits_16(img, edits) } else { apply_edits(img, edits) };
 
     if edits.rotation != 0 {
         processed = rotate_image(processed, edits.rotation);
     }
+    Ok(processed)
+}
 
-    if let Some(resize_val) = options.resize_value {
-        processed = resize_to_fit(processed, resize_val);
+/// Resizes, sharpens, watermarks and encodes one output of a developed image.
+fn write_output(
+    mut processed: DynamicImage,
+    image_path: &str,
+    sidecar_path: &str,
+    dest_path: &Path,
+    rating: u8,
+    options: &ExportOptions,
+) -> Result<Option<u8>, String> {
+    processed = resize_for_export(processed, options);
+    if let Some(sharpening) = &options.output_sharpening {
+        processed = apply_output_sharpening(processed, sharpening);
+    }
+    if let Some(name) = &options.watermark {
+        processed = watermark::apply_watermark(processed, &watermark::load_watermark(name)?)?;
     }
 
     if let Some(parent) = dest_path.parent() {
         fs::create_dir_all(parent).map_err(|e| format!("Create dir failed: {}", e))?;
     }
 
-    match options.format.as_str() {
-        "png" => {
-            processed
-                .save(dest_path)
-                .map_err(|e| format!("Save failed: {}", e))?;
+    let mut metadata = metadata::collect(
+        image_path,
+        sidecar_path,
+        rating,
+        options,
+        processed.width(),
+        processed.height(),
+    );
+    let (encoded, quality) = match options.max_file_size_kb {
+        Some(limit) => encode_within(processed, options, &mut metadata, limit as usize * 1024)?,
+        None => (encode(&processed, options, &metadata)?, options.quality),
+    };
+    write_atomic(dest_path, &encoded)?;
+
+    Ok((options.format == ExportFormat::Jpeg).then_some(quality))
+}
+
+/// Encodes a JPEG no larger than `limit` bytes at the highest quality that
+/// fits, down to `MIN_TARGET_QUALITY` (or the chosen quality, if lower). If even that is too large and
+/// `downscale_to_fit` is set, the image is made smaller until it fits.
+fn encode_within(
+    mut img: DynamicImage,
+    options: &ExportOptions,
+    metadata: &mut ExportMetadata,
+    limit: usize,
+) -> Result<(Vec<u8>, u8), String> {
+    let encode_at = |img: &DynamicImage, metadata: &ExportMetadata, quality: u8| {
+        encode(img, &ExportOptions { quality, ..options.clone() }, metadata)
+    };
+    let min_quality = MIN_TARGET_QUALITY.min(options.quality);
+
+    for _ in 0..=MAX_DOWNSCALE_STEPS {
+        let best = encode_at(&img, metadata, options.quality)?;
+        if best.len() <= limit {
+            return Ok((best, options.quality));
         }
-        _ => {
-            let rgb = processed.to_rgb8();
-            let mut buffer = Cursor::new(Vec::new());
-            let encoder = JpegEncoder::new_with_quality(&mut buffer, options.quality);
-            rgb.write_with_encoder(encoder)
-                .map_err(|e| format!("Encode failed: {}", e))?;
-            fs::write(dest_path, buffer.into_inner())
-                .map_err(|e| format!("Write failed: {}", e))?;
+        let smallest = encode_at(&img, metadata, min_quality)?;
+        if smallest.len() <= limit {
+            // Highest quality that fits, between the two
+            let (mut fits, mut fitting) = (min_quality, smallest);
+            let mut too_large = options.quality;
+            while too_large - fits > 1 {
+                let quality = fits + (too_large - fits) / 2;
+                let encoded = encode_at(&img, metadata, quality)?;
+                if encoded.len() <= limit {
+                    (fits, fitting) = (quality, encoded);
+                } else {
+                    too_large = quality;
+                }
+            }
+            return Ok((fitting, fits));
+        }
+
+        if !options.downscale_to_fit || img.width().min(img.height()) <= MIN_DOWNSCALE_EDGE {
+            return Err(format!(
+                "Output is {} KB at quality {}, over the {} KB limit",
+                smallest.len().div_ceil(1024),
+                min_quality,
+                limit / 1024
+            ));
+        }
+        // File size grows roughly with pixel count
+        let scale = (limit as f64 / smallest.len() as f64).sqrt().clamp(0.5, 0.9);
+        let width = ((img.width() as f64 * scale).round() as u32).max(1);
+        let height = ((img.height() as f64 * scale).round() as u32).max(1);
+        img = img.resize_exact(width, height, FilterType::Lanczos3);
+        metadata.set_dimensions(width, height);
+    }
+    Err(format!("Cannot fit the output in {} KB", limit / 1024))
+}
+
+/// Writes to a hidden temporary file next to `path`, then renames it, so an
+/// interrupted export never leaves a truncated file under the final name.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let temp = path.with_file_name(format!(".{}.tmp", file_name));
+    let written = fs::write(&temp, data).and_then(|_| fs::rename(&temp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(format!("Write failed: {}", e));
+    }
+    Ok(())
+}
+
+/// Lowest JPEG quality tried to meet `max_file_size_kb`; below this the
+/// artifacts are worse than a smaller image.
+const MIN_TARGET_QUALITY: u8 = 30;
+
+/// Times the image is made smaller to meet `max_file_size_kb`.
+const MAX_DOWNSCALE_STEPS: usize = 8;
+
+/// Images are not made smaller than this on the short edge to meet
+/// `max_file_size_kb`.
+const MIN_DOWNSCALE_EDGE: u32 = 64;
+
+/// The recipes of an export: those given, or the options as a single
+/// unnamed recipe. Each is checked before any image is decoded.
+pub fn export_recipes(
+    options: Option<ExportOptions>,
+    recipes: Option<Vec<ExportRecipe>>,
+) -> Result<Vec<ExportRecipe>, String> {
+    let mut recipes = match (options, recipes) {
+        (Some(_), Some(_)) => return Err("Pass options or recipes, not both".to_string()),
+        (Some(options), None) => {
+            validate_options(&options)?;
+            return Ok(vec![ExportRecipe { name: String::new(), options }]);
         }
+        (None, Some(recipes)) if !recipes.is_empty() => recipes,
+        _ => return Err("No export options or recipes".to_string()),
+    };
+
+    let mut names = HashSet::new();
+    for recipe in &mut recipes {
+        recipe.name = recipe.name.trim().to_string();
+        let name = &recipe.name;
+        if name.is_empty() {
+            return Err("Recipe name is empty".to_string());
+        }
+        if !names.insert(name.to_lowercase()) {
+            return Err(format!("Duplicate recipe: {}", name));
+        }
+        validate_options(&recipe.options).map_err(|e| format!("Recipe {}: {}", name, e))?;
     }
+    Ok(recipes)
+}
 
+/// Checks format-specific options before any image is decoded.
+pub fn validate_options(options: &ExportOptions) -> Result<(), String> {
+    let lossy = matches!(options.format, ExportFormat::Jpeg | ExportFormat::Avif);
+    if lossy && !(1..=100).contains(&options.quality) {
+        return Err(format!("Quality must be 1 to 100, got {}", options.quality));
+    }
+    match options.bit_depth {
+        8 => {}
+        16 if matches!(options.format, ExportFormat::Png | ExportFormat::Tiff) => {}
+        16 => return Err(format!("16-bit output is not available for {:?}", options.format)),
+        depth => return Err(format!("Unsupported bit depth: {}", depth)),
+    }
+    match options.max_file_size_kb {
+        Some(0) => return Err("maxFileSizeKb must be positive".to_string()),
+        Some(_) if options.format != ExportFormat::Jpeg => {
+            return Err("maxFileSizeKb is only available for JPEG".to_string())
+        }
+        _ => {}
+    }
+    if options.filename_template.trim().is_empty() {
+        return Err("File name template is empty".to_string());
+    }
+    naming::validate_template(&options.filename_template)?;
+    naming::validate_template(&options.subfolder_template)?;
+    if let Some(name) = &options.watermark {
+        watermark::load_watermark(name)?;
+    }
+    if options.format == ExportFormat::Avif && !(1..=10).contains(&options.avif_speed) {
+        return Err(format!("AVIF speed must be 1 to 10, got {}", options.avif_speed));
+    }
+    match options.resize_mode {
+        ResizeMode::Original => {}
+        ResizeMode::Fit | ResizeMode::Fill => {
+            if !matches!((options.resize_width, options.resize_height), (Some(w), Some(h)) if w > 0 && h > 0) {
+                return Err(format!("{:?} needs resizeWidth and resizeHeight", options.resize_mode));
+            }
+        }
+        mode => {
+            if !options.resize_value.is_some_and(|v| v > 0.0 && v.is_finite()) {
+                return Err(format!("{:?} needs a positive resizeValue", mode));
+            }
+        }
+    }
     Ok(())
 }
+
+/// Scale factor for the resize options, before "don't enlarge".
+fn resize_scale(width: u32, height: u32, options: &ExportOptions) -> Option<f64> {
+    let (w, h) = (width as f64, height as f64);
+    let value = options.resize_value.unwrap_or(0.0) as f64;
+    let boxed = || Some((options.resize_width? as f64 / w, options.resize_height? as f64 / h));
+    Some(match options.resize_mode {
+        ResizeMode::Original => return None,
+        ResizeMode::LongEdge => value / w.max(h),
+        ResizeMode::ShortEdge => value / w.min(h),
+        ResizeMode::Width => value / w,
+        ResizeMode::Height => value / h,
+        ResizeMode::Fit => boxed().map(|(sx, sy)| sx.min(sy))?,
+        ResizeMode::Fill => boxed().map(|(sx, sy)| sx.max(sy))?,
+        ResizeMode::Megapixels => (value * 1e6 / (w * h)).sqrt(),
+        ResizeMode::Percent => value / 100.0,
+    })
+}
+
+/// Resizes with Lanczos3. Fill crops the centre to the box's aspect ratio
+/// first; with "don't enlarge" that crop is kept at its native size.
+fn resize_for_export(img: DynamicImage, options: &ExportOptions) -> DynamicImage {
+    let (width, height) = (img.width(), img.height());
+    let Some(scale) = resize_scale(width, height, options) else {
+        return img;
+    };
+    let scale = if options.dont_enlarge { scale.min(1.0) } else { scale };
+
+    let (img, target) = match (options.resize_mode, options.resize_width, options.resize_height) {
+        (ResizeMode::Fill, Some(box_w), Some(box_h)) => {
+            let cover = (box_w as f64 / width as f64).max(box_h as f64 / height as f64);
+            let crop_w = ((box_w as f64 / cover).round() as u32).clamp(1, width);
+            let crop_h = ((box_h as f64 / cover).round() as u32).clamp(1, height);
+            let cropped = img.crop_imm((width - crop_w) / 2, (height - crop_h) / 2, crop_w, crop_h);
+            let target = if scale < cover {
+                ((crop_w as f64 * scale).round() as u32, (crop_h as f64 * scale).round() as u32)
+            } else {
+                (box_w, box_h)
+            };
+            (cropped, target)
+        }
+        _ => {
+            let target = ((width as f64 * scale).round() as u32, (height as f64 * scale).round() as u32);
+            (img, target)
+        }
+    };
+
+    let target = (target.0.max(1), target.1.max(1));
+    if target == (img.width(), img.height()) {
+        return img;
+    }
+    img.resize_exact(target.0, target.1, FilterType::Lanczos3)
+}
+
+/// Encodes in the chosen format with the metadata embedded. WebP carries
+/// EXIF only and AVIF no metadata, as their encoders have no XMP support.
+fn encode(img: &DynamicImage, options: &ExportOptions, metadata: &ExportMetadata) -> Result<Vec<u8>, String> {
+    let mut buffer = Cursor::new(Vec::new());
+    let encode_err = |e: image::ImageError| format!("Encode failed: {}", e);
+    let exif = metadata.exif.as_ref().map(|exif| exif.to_tiff());
+    let set_exif = |encoder: &mut dyn ImageEncoder| {
+        if let Some(exif) = &exif {
+            encoder.set_exif_metadata(exif.clone()).map_err(|e| format!("Encode failed: {}", e))?;
+        }
+        Ok::<(), String>(())
+    };
+
+    match options.format {
+        ExportFormat::Jpeg => {
+            let mut encoder = JpegEncoder::new_with_quality(&mut buffer, options.quality);
+            set_exif(&mut encoder)?;
+            img.to_rgb8().write_with_encoder(encoder).map_err(encode_err)?;
+        }
+        ExportFormat::Png => {
+            let mut encoder = PngEncoder::new(&mut buffer);
+            set_exif(&mut encoder)?;
+            if options.bit_depth == 16 {
+                img.to_rgb16().write_with_encoder(encoder).map_err(encode_err)?;
+            } else {
+                img.to_rgb8().write_with_encoder(encoder).map_err(encode_err)?;
+            }
+        }
+        ExportFormat::Tiff => write_tiff(&mut buffer, img, options, metadata)
+            .map_err(|e| format!("Encode failed: {}", e))?,
+        ExportFormat::Webp => {
+            let mut encoder = WebPEncoder::new_lossless(&mut buffer);
+            set_exif(&mut encoder)?;
+            img.to_rgb8().write_with_encoder(encoder).map_err(encode_err)?;
+        }
+        ExportFormat::Avif => {
+            let encoder = AvifEncoder::new_with_speed_quality(&mut buffer, options.avif_speed, options.quality);
+            img.to_rgb8().write_with_encoder(encoder).map_err(encode_err)?;
+        }
+    }
+
+    let mut encoded = buffer.into_inner();
+    if let Some(xmp) = &metadata.xmp {
+        match options.format {
+            ExportFormat::Jpeg => metadata::embed_jpeg_xmp(&mut encoded, xmp)?,
+            ExportFormat::Png => metadata::embed_png_xmp(&mut encoded, xmp)?,
+            _ => {}
+        }
+    }
+    Ok(encoded)
+}
+
+fn write_tiff(
+    buffer: &mut Cursor<Vec<u8>>,
+    img: &DynamicImage,
+    options: &ExportOptions,
+    metadata: &ExportMetadata,
+) -> TiffResult<()> {
+    let (compression, predictor) = match options.tiff_compression {
+        TiffCompression::None => (Compression::Uncompressed, Predictor::None),
+        TiffCompression::Lzw => (Compression::Lzw, Predictor::Horizontal),
+        TiffCompression::Deflate => (Compression::Deflate(DeflateLevel::Balanced), Predictor::Horizontal),
+        TiffCompression::Packbits => (Compression::Packbits, Predictor::None),
+    };
+    let mut encoder = TiffEncoder::new(buffer)?
+        .with_compression(compression)
+        .with_predictor(predictor);
+
+    // The EXIF and GPS IFDs are written ahead of the image and linked from
+    // its directory
+    let mut sub_ifds = Vec::new();
+    if let Some(exif) = &metadata.exif {
+        for (ifd, tag) in [(Ifd::Exif, Tag::ExifDirectory), (Ifd::Gps, Tag::GpsDirectory)] {
+            if exif.entries.iter().any(|e| e.ifd == ifd) {
+                let mut directory = encoder.extra_directory()?;
+                metadata::write_tiff_tags(&mut directory, exif, ifd)?;
+                let offset = directory.finish_with_offsets()?;
+                sub_ifds.push((tag, offset.offset));
+            }
+        }
+    }
+
+    let write_tags = |directory: &mut DirectoryEncoder<'_, _, _>| -> TiffResult<()> {
+        if let Some(exif) = &metadata.exif {
+            metadata::write_tiff_tags(directory, exif, Ifd::Primary)?;
+        }
+        for (tag, offset) in &sub_ifds {
+            directory.write_tag(*tag, *offset)?;
+        }
+        if let Some(xmp) = &metadata.xmp {
+            directory.write_tag(Tag::from_u16_exhaustive(TAG_XMP), xmp.as_bytes())?;
+        }
+        Ok(())
+    };
+
+    if options.bit_depth == 16 {
+        let rgb = img.to_rgb16();
+        let mut image = encoder.new_image::<colortype::RGB16>(rgb.width(), rgb.height())?;
+        write_tags(image.encoder())?;
+        image.write_data(rgb.as_raw())
+    } else {
+        let rgb = img.to_rgb8();
+        let mut image = encoder.new_image::<colortype::RGB8>(rgb.width(), rgb.height())?;
+        write_tags(image.encoder())?;
+        image.write_data(rgb.as_raw())
+    }
+}
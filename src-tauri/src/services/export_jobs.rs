@@ -0,0 +1,164 @@
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::models::{EditState, ExportJobState, ExportJobStatus, ExportProgress, ExportRecipe, ExportResult, ImageFile};
+use crate::services::export::{self, FileDestinations};
+
+/// Files exported at once. Each holds a full-size image in memory, and the
+/// per-image filters share the same threads.
+const MAX_THREADS: usize = 4;
+
+/// How often paused workers check whether to go on.
+const PAUSE_POLL: Duration = Duration::from_millis(100);
+
+/// One file of an export, with its inputs copied so the job does not hold
+/// any app state locks. There is a destination for each recipe.
+pub struct ExportItem {
+    pub source_id: String,
+    pub task: Result<(ImageFile, EditState, FileDestinations), String>,
+}
+
+/// Resolves destinations for a snapshot of the files to export, in order.
+/// `None` marks ids that were not found.
+pub fn prepare(
+    snapshot: Vec<(String, Option<(ImageFile, EditState)>)>,
+    destination: &str,
+    recipes: &[ExportRecipe],
+) -> Vec<ExportItem> {
+    let found: Vec<(&ImageFile, &EditState)> = snapshot
+        .iter()
+        .filter_map(|(_, entry)| entry.as_ref().map(|(file, edits)| (file, edits)))
+        .collect();
+    let mut planned = export::plan_destinations(&found, destination, recipes).into_iter();
+
+    snapshot
+        .into_iter()
+        .map(|(source_id, entry)| {
+            let task = match entry {
+                Some((file, edits)) => match planned.next() {
+                    Some(destinations) => Ok((file, edits, destinations)),
+                    None => unreachable!("a destination is planned for each file found"),
+                },
+                None => Err("File not found".to_string()),
+            };
+            ExportItem { source_id, task }
+        })
+        .collect()
+}
+
+/// A running or finished export, counted in outputs: one per file and
+/// recipe. Results are kept in the order the files were given, then in
+/// recipe order.
+pub struct ExportJob {
+    pub id: String,
+    total: usize,
+    results: Mutex<Vec<Option<ExportResult>>>,
+    completed: AtomicUsize,
+    cancelled: AtomicBool,
+    paused: AtomicBool,
+    /// When every output had a result.
+    finished: OnceLock<Instant>,
+}
+
+impl ExportJob {
+    pub fn new(total: usize) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            total,
+            results: Mutex::new(vec![None; total]),
+            completed: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            finished: OnceLock::new(),
+        }
+    }
+
+    /// Time since the job finished; `None` while it is running.
+    pub fn finished_for(&self) -> Option<Duration> {
+        self.finished.get().map(Instant::elapsed)
+    }
+
+    /// Files not started yet are reported as cancelled; those in progress
+    /// are finished.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Stops starting new files until resumed.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    pub fn status(&self) -> ExportJobStatus {
+        let results = self.results.lock().unwrap();
+        let state = if self.finished.get().is_some() {
+            if self.cancelled.load(Ordering::SeqCst) {
+                ExportJobState::Cancelled
+            } else {
+                ExportJobState::Completed
+            }
+        } else if self.paused.load(Ordering::SeqCst) && !self.cancelled.load(Ordering::SeqCst) {
+            ExportJobState::Paused
+        } else {
+            ExportJobState::Running
+        };
+        ExportJobStatus {
+            job_id: self.id.clone(),
+            state,
+            total: self.total,
+            completed: self.completed.load(Ordering::SeqCst),
+            results: results.iter().flatten().cloned().collect(),
+        }
+    }
+
+    /// Exports the items on a bounded thread pool, calling `on_progress`
+    /// for each output. Blocks until every output has a result.
+    pub fn run(&self, items: Vec<ExportItem>, recipes: &[ExportRecipe], on_progress: impl Fn(ExportProgress) + Sync) {
+        let threads = thread::available_parallelism().map_or(2, |n| n.get()).min(MAX_THREADS);
+        let export_all = || {
+            items.into_par_iter().enumerate().for_each(|(index, item)| {
+                let results = self.export_item(item, recipes);
+                for (offset, result) in results.into_iter().enumerate() {
+                    self.results.lock().unwrap()[index * recipes.len() + offset] = Some(result.clone());
+                    let completed = self.completed.fetch_add(1, Ordering::SeqCst) + 1;
+                    on_progress(ExportProgress {
+                        job_id: self.id.clone(),
+                        completed,
+                        total: self.total,
+                        result,
+                    });
+                }
+            })
+        };
+
+        match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+            Ok(pool) => pool.install(export_all),
+            Err(e) => {
+                tracing::warn!("Export pool unavailable, using the global pool: {}", e);
+                export_all()
+            }
+        }
+        let _ = self.finished.set(Instant::now());
+    }
+
+    fn export_item(&self, item: ExportItem, recipes: &[ExportRecipe]) -> Vec<ExportResult> {
+        while self.paused.load(Ordering::SeqCst) && !self.cancelled.load(Ordering::SeqCst) {
+            thread::sleep(PAUSE_POLL);
+        }
+        let error = if self.cancelled.load(Ordering::SeqCst) {
+            "Cancelled".to_string()
+        } else {
+            match item.task {
+                Ok((file, edits, destinations)) => return export::export_image(&file, &destinations, &edits, recipes),
+                Err(e) => e,
+            }
+        };
+        recipes
+            .iter()
+            .map(|recipe| export::failed_result(&item.source_id, recipe, error.clone()))
+            .collect()
+    }
+}
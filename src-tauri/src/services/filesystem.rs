@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use walkdir::WalkDir;
@@ -57,6 +57,7 @@ pub fn scan_directory(path: &Path) -> Result<Vec<ImageFile>, String> {
             modified_at,
             is_raw: IF::is_raw_extension(&extension),
             dimensions: None,
+            virtual_copy: None,
         });
     }
 
@@ -68,7 +69,7 @@ pub fn load_sidecars(files: &[ImageFile]) -> HashMap<String, EditState> {
     let mut states = HashMap::new();
 
     for file in files {
-        let xmp_path = get_xmp_path(&file.path);
+        let xmp_path = get_sidecar_path(file);
         if let Ok(content) = fs::read_to_string(&xmp_path) {
             if let Ok(edit_state) = crate::services::xmp::parse_xmp(&content) {
                 states.insert(file.id.clone(), edit_state);
@@ -86,6 +87,72 @@ pub fn get_xmp_path(image_path: &str) -> String {
     parent.join(format!("{}.xmp", stem)).to_string_lossy().to_string()
 }
 
+/// Sidecar holding a file's edits. Virtual copies use `{stem}.copy{N}.xmp`
+/// next to the original's sidecar.
+pub fn get_sidecar_path(file: &ImageFile) -> String {
+    match file.virtual_copy {
+        Some(n) => {
+            let path = Path::new(&file.path);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let parent = path.parent().unwrap_or(Path::new("."));
+            parent.join(format!("{}.copy{}.xmp", stem, n)).to_string_lossy().to_string()
+        }
+        None => get_xmp_path(&file.path),
+    }
+}
+
+/// Virtual copies recorded by `{stem}.copy{N}.xmp` sidecars next to the
+/// files, ordered by copy number.
+pub fn find_virtual_copies(files: &[ImageFile]) -> Vec<ImageFile> {
+    let mut sidecars: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    // A RAW+JPEG pair shares its sidecars; the copies go with the first
+    let mut claimed: HashSet<PathBuf> = HashSet::new();
+    let mut copies = Vec::new();
+
+    for file in files.iter().filter(|f| f.virtual_copy.is_none()) {
+        let path = Path::new(&file.path);
+        let parent = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        if !claimed.insert(parent.join(&stem)) {
+            continue;
+        }
+
+        let names = sidecars.entry(parent.clone()).or_insert_with(|| {
+            fs::read_dir(&parent)
+                .map(|entries| {
+                    entries
+                        .filter_map(|e| e.ok())
+                        .map(|e| e.file_name().to_string_lossy().to_string())
+                        .filter(|n| n.ends_with(".xmp"))
+                        .collect()
+                })
+                .unwrap_or_default()
+        });
+
+        let prefix = format!("{}.copy", stem);
+        let mut numbers: Vec<u32> = names
+            .iter()
+            .filter_map(|n| n.strip_prefix(&prefix)?.strip_suffix(".xmp")?.parse().ok())
+            .filter(|n| *n > 0)
+            .collect();
+        numbers.sort_unstable();
+        for n in numbers {
+            copies.push(ImageFile {
+                id: Uuid::new_v4().to_string(),
+                virtual_copy: Some(n),
+                ..file.clone()
+            });
+        }
+    }
+
+    copies
+}
+
+/// Edit history kept next to the XMP sidecar.
+pub fn get_history_path(sidecar_path: &str) -> String {
+    Path::new(sidecar_path).with_extension("history.json").to_string_lossy().to_string()
+}
+
 pub fn get_cache_dir() -> std::path::PathBuf {
     std::env::var("PHOTOCULL_CACHE_DIR")
         .map(std::path::PathBuf::from)
@@ -99,3 +166,33 @@ pub fn get_cache_dir() -> std::path::PathBuf {
 pub fn get_thumbnail_dir() -> std::path::PathBuf {
     get_cache_dir().join("thumbnails")
 }
+
+pub fn get_config_dir() -> std::path::PathBuf {
+    std::env::var("PHOTOCULL_CONFIG_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|_| {
+            dirs::config_dir()
+                .unwrap_or_else(|| std::path::PathBuf::from("."))
+                .join("photocull")
+        })
+}
+
+/// Directory of lensfun XML database files.
+pub fn get_lens_profile_dir() -> std::path::PathBuf {
+    get_config_dir().join("lenses")
+}
+
+/// Directory of `.cube` and HaldCLUT looks.
+pub fn get_looks_dir() -> std::path::PathBuf {
+    get_config_dir().join("looks")
+}
+
+/// Directory of edit presets, stored as XMP.
+pub fn get_presets_dir() -> std::path::PathBuf {
+    get_config_dir().join("presets")
+}
+
+/// Directory of watermark presets, stored as JSON next to their images.
+pub fn get_watermarks_dir() -> std::path::PathBuf {
+    get_config_dir().join("watermarks")
+}
@@ -0,0 +1,305 @@
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+/// Gaussian blur of a single-channel plane. Small radii use an exact
+/// separable kernel; larger ones are approximated by three box blurs so the
+/// cost is independent of `sigma`.
+pub fn gaussian_blur(plane: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    if sigma < 0.2 || width == 0 || height == 0 {
+        return plane.to_vec();
+    }
+    if sigma < 3.0 {
+        return kernel_blur(plane, width, height, sigma);
+    }
+
+    let mut current = plane.to_vec();
+    for radius in box_radii(sigma) {
+        current = box_blur(&current, width, height, radius);
+    }
+    current
+}
+
+fn kernel_blur(plane: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let radius = (sigma * 3.0).ceil() as usize;
+    let mut kernel: Vec<f32> = (0..=2 * radius)
+        .map(|i| {
+            let d = i as f32 - radius as f32;
+            (-d * d / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|k| *k /= total);
+
+    let horizontal = convolve_rows(plane, width, &kernel);
+    let transposed = transpose(&horizontal, width, height);
+    let vertical = convolve_rows(&transposed, height, &kernel);
+    transpose(&vertical, height, width)
+}
+
+fn convolve_rows(plane: &[f32], width: usize, kernel: &[f32]) -> Vec<f32> {
+    let mut out = vec![0.0; plane.len()];
+    let radius = kernel.len() / 2;
+    let last = width as isize - 1;
+
+    out.par_chunks_mut(width)
+        .zip(plane.par_chunks(width))
+        .for_each(|(dst, src)| {
+            for (x, value) in dst.iter_mut().enumerate() {
+                *value = kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, weight)| {
+                        let sx = (x as isize + k as isize - radius as isize).clamp(0, last);
+                        src[sx as usize] * weight
+                    })
+                    .sum();
+            }
+        });
+    out
+}
+
+/// Separable box blur with edge clamping.
+pub fn box_blur(plane: &[f32], width: usize, height: usize, radius: usize) -> Vec<f32> {
+    if radius == 0 {
+        return plane.to_vec();
+    }
+    let horizontal = box_blur_rows(plane, width, radius);
+    let transposed = transpose(&horizontal, width, height);
+    let vertical = box_blur_rows(&transposed, height, radius);
+    transpose(&vertical, height, width)
+}
+
+fn box_blur_rows(plane: &[f32], width: usize, radius: usize) -> Vec<f32> {
+    let mut out = vec![0.0; plane.len()];
+    let norm = 1.0 / (2 * radius + 1) as f32;
+    let last = width - 1;
+
+    out.par_chunks_mut(width)
+        .zip(plane.par_chunks(width))
+        .for_each(|(dst, src)| {
+            let mut sum: f32 = (0..=2 * radius)
+                .map(|i| src[i.saturating_sub(radius).min(last)])
+                .sum();
+            for x in 0..width {
+                dst[x] = sum * norm;
+                let add = src[(x + radius + 1).min(last)];
+                let sub = src[x.saturating_sub(radius)];
+                sum += add - sub;
+            }
+        });
+    out
+}
+
+fn transpose(plane: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut out = vec![0.0; plane.len()];
+    out.par_chunks_mut(height).enumerate().for_each(|(x, column)| {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = plane[y * width + x];
+        }
+    });
+    out
+}
+
+/// Box radii for a three-pass approximation of a Gaussian with `sigma`.
+fn box_radii(sigma: f32) -> [usize; 3] {
+    let n = 3.0;
+    let ideal = (12.0 * sigma * sigma / n + 1.0).sqrt();
+    let mut lower = ideal.floor() as i32;
+    if lower % 2 == 0 {
+        lower -= 1;
+    }
+    let upper = lower + 2;
+    let lw = lower as f32;
+    let m = ((12.0 * sigma * sigma - n * lw * lw - 4.0 * n * lw - 3.0 * n) / (-4.0 * lw - 4.0)).round() as i32;
+
+    let mut radii = [0; 3];
+    for (i, radius) in radii.iter_mut().enumerate() {
+        let size = if (i as i32) < m { lower } else { upper };
+        *radius = ((size - 1) / 2).max(0) as usize;
+    }
+    radii
+}
+
+/// Rec. 601 luma on the 0 to 255 scale, matching the weights used elsewhere
+/// in the pipeline.
+pub fn luma_plane<T: Copy + Into<f32> + Sync>(pixels: &[[T; 3]]) -> Vec<f32> {
+    pixels
+        .par_iter()
+        .map(|p| 0.299 * p[0].into() + 0.587 * p[1].into() + 0.114 * p[2].into())
+        .collect()
+}
+
+/// Grayscale erosion (minimum over a square window) using a monotonic deque
+/// per row and column, so cost does not depend on `radius`.
+pub fn min_filter(plane: &[f32], width: usize, height: usize, radius: usize) -> Vec<f32> {
+    if radius == 0 {
+        return plane.to_vec();
+    }
+    let horizontal = min_filter_rows(plane, width, radius);
+    let transposed = transpose(&horizontal, width, height);
+    let vertical = min_filter_rows(&transposed, height, radius);
+    transpose(&vertical, height, width)
+}
+
+fn min_filter_rows(plane: &[f32], width: usize, radius: usize) -> Vec<f32> {
+    let mut out = vec![0.0; plane.len()];
+    out.par_chunks_mut(width)
+        .zip(plane.par_chunks(width))
+        .for_each(|(dst, src)| {
+            let mut window: VecDeque<usize> = VecDeque::new();
+            let mut next = 0;
+            for (x, value) in dst.iter_mut().enumerate() {
+                let hi = (x + radius).min(width - 1);
+                while next <= hi {
+                    while window.back().is_some_and(|&i| src[i] >= src[next]) {
+                        window.pop_back();
+                    }
+                    window.push_back(next);
+                    next += 1;
+                }
+                while window.front().is_some_and(|&i| i + radius < x) {
+                    window.pop_front();
+                }
+                *value = src[window[0]];
+            }
+        });
+    out
+}
+
+/// Edge-preserving smoothing of `input` steered by `guide` (He et al.). Both
+/// planes should be on the same 0 to 1 scale as `eps`.
+pub fn guided_filter(
+    guide: &[f32],
+    input: &[f32],
+    width: usize,
+    height: usize,
+    radius: usize,
+    eps: f32,
+) -> Vec<f32> {
+    let mean_i = box_blur(guide, width, height, radius);
+    let mean_p = box_blur(input, width, height, radius);
+    let ii: Vec<f32> = guide.par_iter().map(|v| v * v).collect();
+    let ip: Vec<f32> = guide.par_iter().zip(input).map(|(g, p)| g * p).collect();
+    let corr_i = box_blur(&ii, width, height, radius);
+    let corr_ip = box_blur(&ip, width, height, radius);
+
+    let (a, b): (Vec<f32>, Vec<f32>) = (0..guide.len())
+        .into_par_iter()
+        .map(|i| {
+            let var = corr_i[i] - mean_i[i] * mean_i[i];
+            let cov = corr_ip[i] - mean_i[i] * mean_p[i];
+            let a = cov / (var + eps);
+            (a, mean_p[i] - a * mean_i[i])
+        })
+        .unzip();
+
+    let mean_a = box_blur(&a, width, height, radius);
+    let mean_b = box_blur(&b, width, height, radius);
+    (0..guide.len())
+        .into_par_iter()
+        .map(|i| mean_a[i] * guide[i] + mean_b[i])
+        .collect()
+}
+
+/// Band-pass detail of a luma plane: the difference between a blur at
+/// `fine_sigma` (0 keeps full detail) and one at `coarse_sigma`.
+pub fn detail_band(luma: &[f32], width: usize, height: usize, fine_sigma: f32, coarse_sigma: f32) -> Vec<f32> {
+    let fine = if fine_sigma > 0.0 {
+        gaussian_blur(luma, width, height, fine_sigma)
+    } else {
+        luma.to_vec()
+    };
+    let coarse = gaussian_blur(luma, width, height, coarse_sigma);
+    fine.par_iter().zip(&coarse).map(|(f, c)| f - c).collect()
+}
+
+/// Luma minus its large-radius blur: the band clarity boosts.
+pub fn clarity_detail(pixels: &[[f32; 3]], width: usize, height: usize) -> Vec<f32> {
+    let luma = luma_plane(pixels);
+    detail_band(&luma, width, height, 0.0, width.max(height) as f32 * 0.01)
+}
+
+/// Standard deviation of noise in each à trous detail level, relative to the
+/// image noise, for the B3-spline kernel.
+const ATROUS_NOISE: [f32; 6] = [0.890, 0.201, 0.086, 0.041, 0.020, 0.010];
+
+/// Wavelet denoising: decomposes the plane with an à trous B3-spline
+/// transform and soft-thresholds each detail level. Strong edges produce large
+/// coefficients and survive; noise is estimated from the finest level so the
+/// same `strength` behaves alike on previews and full-resolution renders.
+/// `strength` and `detail` are 0 to 1; higher detail keeps more fine texture.
+pub fn wavelet_denoise(
+    plane: &[f32],
+    width: usize,
+    height: usize,
+    levels: usize,
+    strength: f32,
+    detail: f32,
+) -> Vec<f32> {
+    let levels = levels.clamp(1, ATROUS_NOISE.len());
+    if strength <= 0.0 || width < 2 || height < 2 {
+        return plane.to_vec();
+    }
+
+    let mut current = plane.to_vec();
+    let mut result = vec![0.0; plane.len()];
+    let mut noise_sigma = None;
+
+    for (level, relative_noise) in ATROUS_NOISE.iter().enumerate().take(levels) {
+        let smooth = atrous_blur(&current, width, height, 1 << level);
+        let coefficients: Vec<f32> = current.par_iter().zip(&smooth).map(|(c, s)| c - s).collect();
+
+        // Floor keeps the slider effective on clean or heavily quantized input
+        let sigma = *noise_sigma.get_or_insert_with(|| (estimate_noise(&coefficients) / ATROUS_NOISE[0]).max(1.0));
+        // Finer levels carry texture, so detail relaxes their thresholds most
+        let keep = detail * 0.8 / (level + 1) as f32;
+        let threshold = 3.0 * strength * sigma * relative_noise * (1.0 - keep);
+
+        result.par_iter_mut().zip(coefficients.par_iter()).for_each(|(r, &c)| {
+            *r += c.signum() * (c.abs() - threshold).max(0.0);
+        });
+        current = smooth;
+    }
+
+    result.par_iter_mut().zip(current.par_iter()).for_each(|(r, c)| *r += c);
+    result
+}
+
+/// Median absolute deviation estimate of Gaussian noise.
+fn estimate_noise(coefficients: &[f32]) -> f32 {
+    let mut magnitudes: Vec<f32> = coefficients.iter().map(|c| c.abs()).collect();
+    let mid = magnitudes.len() / 2;
+    let (_, median, _) = magnitudes.select_nth_unstable_by(mid, |a, b| a.total_cmp(b));
+    *median / 0.6745
+}
+
+/// One à trous smoothing step: the B3-spline kernel with `step - 1` holes.
+fn atrous_blur(plane: &[f32], width: usize, height: usize, step: usize) -> Vec<f32> {
+    let horizontal = atrous_rows(plane, width, step);
+    let transposed = transpose(&horizontal, width, height);
+    let vertical = atrous_rows(&transposed, height, step);
+    transpose(&vertical, height, width)
+}
+
+fn atrous_rows(plane: &[f32], width: usize, step: usize) -> Vec<f32> {
+    const KERNEL: [f32; 5] = [1.0 / 16.0, 4.0 / 16.0, 6.0 / 16.0, 4.0 / 16.0, 1.0 / 16.0];
+    let mut out = vec![0.0; plane.len()];
+    let last = width as isize - 1;
+
+    out.par_chunks_mut(width)
+        .zip(plane.par_chunks(width))
+        .for_each(|(dst, src)| {
+            for (x, value) in dst.iter_mut().enumerate() {
+                *value = KERNEL
+                    .iter()
+                    .enumerate()
+                    .map(|(k, weight)| {
+                        let offset = (k as isize - 2) * step as isize;
+                        let sx = (x as isize + offset).clamp(0, last);
+                        src[sx as usize] * weight
+                    })
+                    .sum();
+            }
+        });
+    out
+}
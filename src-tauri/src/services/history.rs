@@ -0,0 +1,139 @@
+use std::fs;
+
+use chrono::Utc;
+
+use crate::models::{EditHistory, EditState, HistoryEntry, Snapshot};
+use crate::services::filesystem::get_history_path;
+
+/// Oldest steps are dropped beyond this.
+const MAX_ENTRIES: usize = 100;
+
+pub fn load_history(sidecar_path: &str) -> EditHistory {
+    let Ok(content) = fs::read_to_string(get_history_path(sidecar_path)) else {
+        return EditHistory::default();
+    };
+    match serde_json::from_str::<EditHistory>(&content) {
+        Ok(mut history) => {
+            history.position = history.position.min(history.entries.len().saturating_sub(1));
+            history
+        }
+        Err(e) => {
+            tracing::warn!("Ignoring unreadable history for {}: {}", sidecar_path, e);
+            EditHistory::default()
+        }
+    }
+}
+
+fn save_history(sidecar_path: &str, history: &EditHistory) -> Result<(), String> {
+    let content = serde_json::to_string(history).map_err(|e| e.to_string())?;
+    fs::write(get_history_path(sidecar_path), content).map_err(|e| format!("Write failed: {}", e))
+}
+
+fn same_edits(a: &EditState, b: &EditState) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+/// Adds a step for a change from `previous` to `edits`, discarding any steps
+/// that could have been redone. The first change also records `previous` as
+/// the original state so it can be undone.
+pub fn record(sidecar_path: &str, previous: &EditState, edits: &EditState, label: &str) -> Result<(), String> {
+    let mut history = load_history(sidecar_path);
+    let now = Utc::now().to_rfc3339();
+
+    match history.entries.get(history.position) {
+        Some(current) if same_edits(&current.edits, edits) => return Ok(()),
+        Some(_) => history.entries.truncate(history.position + 1),
+        None if same_edits(previous, edits) => return Ok(()),
+        None => history.entries.push(HistoryEntry {
+            label: "Original".to_string(),
+            timestamp: now.clone(),
+            edits: previous.clone(),
+        }),
+    }
+
+    history.entries.push(HistoryEntry {
+        label: label.to_string(),
+        timestamp: now,
+        edits: edits.clone(),
+    });
+    if history.entries.len() > MAX_ENTRIES {
+        history.entries.drain(..history.entries.len() - MAX_ENTRIES);
+    }
+    history.position = history.entries.len() - 1;
+    save_history(sidecar_path, &history)
+}
+
+/// Moves to the given step. `apply` makes its edit state current (e.g. writes
+/// the sidecar); the position only moves once that succeeds, so a failed
+/// write leaves the history where it was.
+pub fn go_to<T>(
+    sidecar_path: &str,
+    index: usize,
+    apply: impl FnOnce(EditState) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut history = load_history(sidecar_path);
+    let entry = history.entries.get(index).ok_or("Invalid history step")?;
+    let applied = apply(entry.edits.clone())?;
+    history.position = index;
+    // The edits are already current; a stale position only affects what
+    // undo and redo step to next
+    if let Err(e) = save_history(sidecar_path, &history) {
+        tracing::warn!("Failed to save history position for {}: {}", sidecar_path, e);
+    }
+    Ok(applied)
+}
+
+pub fn undo<T>(sidecar_path: &str, apply: impl FnOnce(EditState) -> Result<T, String>) -> Result<T, String> {
+    let history = load_history(sidecar_path);
+    if history.position == 0 {
+        return Err("Nothing to undo".to_string());
+    }
+    go_to(sidecar_path, history.position - 1, apply)
+}
+
+pub fn redo<T>(sidecar_path: &str, apply: impl FnOnce(EditState) -> Result<T, String>) -> Result<T, String> {
+    let history = load_history(sidecar_path);
+    if history.position + 1 >= history.entries.len() {
+        return Err("Nothing to redo".to_string());
+    }
+    go_to(sidecar_path, history.position + 1, apply)
+}
+
+/// Saves `edits` as a named snapshot, replacing one with the same name.
+pub fn create_snapshot(sidecar_path: &str, name: &str, edits: &EditState) -> Result<Snapshot, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Snapshot name is empty".to_string());
+    }
+    let snapshot = Snapshot {
+        name: name.to_string(),
+        timestamp: Utc::now().to_rfc3339(),
+        edits: edits.clone(),
+    };
+
+    let mut history = load_history(sidecar_path);
+    history.snapshots.retain(|s| s.name != name);
+    history.snapshots.push(snapshot.clone());
+    save_history(sidecar_path, &history)?;
+    Ok(snapshot)
+}
+
+pub fn find_snapshot(sidecar_path: &str, name: &str) -> Result<Snapshot, String> {
+    let name = name.trim();
+    load_history(sidecar_path)
+        .snapshots
+        .into_iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| format!("Snapshot not found: {}", name))
+}
+
+pub fn delete_snapshot(sidecar_path: &str, name: &str) -> Result<(), String> {
+    let name = name.trim();
+    let mut history = load_history(sidecar_path);
+    let count = history.snapshots.len();
+    history.snapshots.retain(|s| s.name != name);
+    if history.snapshots.len() == count {
+        return Err(format!("Snapshot not found: {}", name));
+    }
+    save_history(sidecar_path, &history)
+}
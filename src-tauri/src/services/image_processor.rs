@@ -1,19 +1,85 @@
 use image::{DynamicImage, ImageBuffer, Rgb, RgbImage};
 use rayon::prelude::*;
 
-use crate::models::EditState;
+use crate::models::{EditState, OutputSharpening, SampleRegion, SharpenAmount, SharpenMedium};
+use crate::services::color_mixer::{ColorMixer, GrayMixer, SplitToner};
+use crate::services::effects::{apply_grain, apply_vignette};
+use crate::services::filters::{clarity_detail, detail_band, gaussian_blur, guided_filter, luma_plane, min_filter, wavelet_denoise};
+use crate::services::lut;
+use crate::services::masks::apply_masks;
+use crate::services::tone_curve::ToneCurveLut;
+use crate::services::white_balance;
 
 pub fn apply_edits(img: DynamicImage, edits: &EditState) -> DynamicImage {
-    let rgb = img.to_rgb8();
-    let (width, height) = rgb.dimensions();
-    let mut pixels: Vec<[u8; 3]> = rgb.pixels().map(|p| p.0).collect();
+    apply_edits_at_scale(img, edits, 1.0)
+}
+
+/// Like `apply_edits`, but returns 16 bits per channel for high bit depth
+/// output instead of rounding to 8.
+pub fn apply_edits_16(img: DynamicImage, edits: &EditState) -> DynamicImage {
+    render_edits(img, edits, 1.0, true)
+}
+
+/// Applies edits to an image rendered at `scale` times the source resolution
+/// (e.g. 0.25 for a preview of a 4x larger original), so radius-based tools
+/// look the same on screen as in a full-resolution export.
+pub fn apply_edits_at_scale(img: DynamicImage, edits: &EditState, scale: f32) -> DynamicImage {
+    render_edits(img, edits, scale, false)
+}
+
+/// The edit pipeline. Pixels stay in floating point on the 0 to 255 scale
+/// through every step and are only rounded to 8 or 16 bits at the end.
+fn render_edits(img: DynamicImage, edits: &EditState, scale: f32, high_depth: bool) -> DynamicImage {
+    let scale = if scale.is_finite() && scale > 0.0 { scale.min(1.0) } else { 1.0 };
+    // Float sources (developed RAW) may exceed 255: highlight headroom the
+    // highlights slider can bring back into range
+    let source = img.to_rgb32f();
+    let (width, height) = source.dimensions();
+    let mut pixels: Vec<[f32; 3]> = source.pixels().map(|p| p.0.map(|v| v * 255.0)).collect();
+    drop(source);
+
+    // Dehaze works on scene values, so it runs before the tonal adjustments
+    if edits.dehaze != 0.0 && width > 2 && height > 2 {
+        apply_dehaze(&mut pixels, width, height, edits.dehaze);
+    }
+
+    let wb_matrix = white_balance::adaptation_matrix(edits.white_balance_temp, edits.white_balance_tint);
+    let tone_lut = ToneCurveLut::new(&edits.tone_curve);
+    let color_mixer = ColorMixer::new(&edits.hsl);
+    let gray_mixer = edits.monochrome.then(|| GrayMixer::new(&edits.gray_mixer));
+    let split_toner = SplitToner::new(&edits.split_toning);
+    let look_amount = edits.look.amount.clamp(0.0, 200.0) / 100.0;
+    let look = edits
+        .look
+        .name
+        .as_deref()
+        .filter(|_| look_amount > 0.0)
+        .and_then(|name| match lut::load_look(name) {
+            Ok(lut) => Some(lut),
+            Err(e) => {
+                tracing::warn!("Skipping look {}: {}", name, e);
+                None
+            }
+        });
+    let highlights = edits.highlights.clamp(-100.0, 100.0) / 100.0;
 
     // Apply all edits in a single parallel pass
     pixels.par_chunks_mut(1024).for_each(|chunk| {
         for pixel in chunk.iter_mut() {
-            let mut r = pixel[0] as f32;
-            let mut g = pixel[1] as f32;
-            let mut b = pixel[2] as f32;
+            let [mut r, mut g, mut b] = *pixel;
+
+            // White balance (chromatic adaptation in linear light)
+            if let Some(m) = &wb_matrix {
+                let lin = [r, g, b].map(|v| white_balance::srgb_to_linear(v.max(0.0) / 255.0));
+                let adapted = [
+                    m[0][0] * lin[0] + m[0][1] * lin[1] + m[0][2] * lin[2],
+                    m[1][0] * lin[0] + m[1][1] * lin[1] + m[1][2] * lin[2],
+                    m[2][0] * lin[0] + m[2][1] * lin[1] + m[2][2] * lin[2],
+                ];
+                r = white_balance::linear_to_srgb(adapted[0].max(0.0)) * 255.0;
+                g = white_balance::linear_to_srgb(adapted[1].max(0.0)) * 255.0;
+                b = white_balance::linear_to_srgb(adapted[2].max(0.0)) * 255.0;
+            }
 
             // Exposure
             if edits.exposure != 0.0 {
@@ -31,14 +97,22 @@ pub fn apply_edits(img: DynamicImage, edits: &EditState) -> DynamicImage {
                 b = factor * (b - 128.0) + 128.0;
             }
 
-            // Highlights
-            if edits.highlights != 0.0 {
+            // Highlights: negative recovers, rolling values above a knee
+            // (including headroom above white) off smoothly toward white
+            if highlights != 0.0 {
                 let lum = 0.299 * r + 0.587 * g + 0.114 * b;
-                let mask = ((lum - 128.0) / 127.0).clamp(0.0, 1.0);
-                let adj = -(edits.highlights / 100.0) * mask * 0.5;
-                r *= 1.0 + adj;
-                g *= 1.0 + adj;
-                b *= 1.0 + adj;
+                let target = if highlights < 0.0 {
+                    recover_highlights(lum, -highlights)
+                } else {
+                    let mask = ((lum - 128.0) / 127.0).clamp(0.0, 1.0);
+                    lum * (1.0 + highlights * mask * 0.5)
+                };
+                if lum > 0.0 {
+                    let factor = target / lum;
+                    r *= factor;
+                    g *= factor;
+                    b *= factor;
+                }
             }
 
             // Shadows
@@ -51,13 +125,11 @@ pub fn apply_edits(img: DynamicImage, edits: &EditState) -> DynamicImage {
                 b *= 1.0 + adj;
             }
 
-            // White balance
-            if edits.white_balance_temp != 5500.0 || edits.white_balance_tint != 0.0 {
-                let temp_shift = (edits.white_balance_temp - 5500.0) / 100.0;
-                let tint_shift = edits.white_balance_tint / 100.0;
-                r += temp_shift;
-                g -= tint_shift * 10.0;
-                b -= temp_shift;
+            // Tone curve
+            if let Some(lut) = &tone_lut {
+                r = lut.apply(0, r);
+                g = lut.apply(1, g);
+                b = lut.apply(2, b);
             }
 
             // Saturation
@@ -81,93 +153,262 @@ pub fn apply_edits(img: DynamicImage, edits: &EditState) -> DynamicImage {
                 b = gray + (1.0 + adj) * (b - gray);
             }
 
-            pixel[0] = r.clamp(0.0, 255.0) as u8;
-            pixel[1] = g.clamp(0.0, 255.0) as u8;
-            pixel[2] = b.clamp(0.0, 255.0) as u8;
+            // Color mixer (per-hue HSL), or the gray mixer in monochrome
+            if let Some(gray_mixer) = &gray_mixer {
+                let gray = gray_mixer.apply(r, g, b);
+                (r, g, b) = (gray, gray, gray);
+            } else if let Some(mixer) = &color_mixer {
+                (r, g, b) = mixer.apply(r, g, b);
+            }
+
+            // Split toning
+            if let Some(toner) = &split_toner {
+                (r, g, b) = toner.apply(r, g, b);
+            }
+
+            // Creative look (3D LUT), blended by amount
+            if let Some(lut) = &look {
+                let input = [r, g, b].map(|v| v.clamp(0.0, 255.0) / 255.0);
+                let graded = lut.sample(input, edits.look.interpolation);
+                r += (graded[0] * 255.0 - r) * look_amount;
+                g += (graded[1] * 255.0 - g) * look_amount;
+                b += (graded[2] * 255.0 - b) * look_amount;
+            }
+
+            *pixel = [r, g, b].map(|v| v.clamp(0.0, 255.0));
         }
     });
 
+    // Clarity and texture (local contrast, needs neighbor access)
+    if (edits.clarity != 0.0 || edits.texture != 0.0) && width > 2 && height > 2 {
+        apply_local_contrast(&mut pixels, width, height, edits.clarity, edits.texture);
+    }
+
+    // Local adjustments, composited over the global result
+    if !edits.masks.is_empty() {
+        apply_masks(&mut pixels, width, height, &edits.masks);
+    }
+
+    // Noise reduction before sharpening so noise is not amplified
+    if (edits.noise_reduction > 0.0 || edits.color_noise_reduction > 0.0) && width > 2 && height > 2 {
+        apply_noise_reduction(&mut pixels, width, height, edits);
+    }
+
     // Apply sharpening (needs neighbor access, separate pass)
     if edits.sharpening_amount > 0.0 && width > 2 && height > 2 {
-        pixels = apply_sharpening_parallel(&pixels, width, height, edits.sharpening_amount);
+        apply_sharpening(&mut pixels, width, height, edits, scale);
     }
 
-    // Apply noise reduction (needs neighbor access, separate pass)
-    if edits.noise_reduction > 0.0 && width > 2 && height > 2 {
-        pixels = apply_noise_reduction_parallel(&pixels, width, height, edits.noise_reduction);
+    // Effects apply to the finished (cropped) frame
+    apply_vignette(&mut pixels, width, height, &edits.vignette);
+    apply_grain(&mut pixels, width, height, &edits.grain, scale);
+
+    from_pixels(width, height, &pixels, high_depth)
+}
+
+/// Whether an image holds more than 8 bits per channel.
+pub fn is_high_depth(img: &DynamicImage) -> bool {
+    let color = img.color();
+    color.bytes_per_pixel() > color.channel_count()
+}
+
+/// RGB pixels on the 0 to 255 scale the edit pipeline works in.
+fn to_pixels(img: &DynamicImage) -> Vec<[f32; 3]> {
+    img.to_rgb32f().pixels().map(|p| p.0.map(|v| v * 255.0)).collect()
+}
+
+/// Rounds pixels on the 0 to 255 scale to an 8-bit image, or a 16-bit one
+/// when `high_depth` is set.
+fn from_pixels(width: u32, height: u32, pixels: &[[f32; 3]], high_depth: bool) -> DynamicImage {
+    let pixel = |x: u32, y: u32| pixels[(y * width + x) as usize].map(|v| v.clamp(0.0, 255.0) / 255.0);
+    if high_depth {
+        let result: ImageBuffer<Rgb<u16>, Vec<u16>> =
+            ImageBuffer::from_fn(width, height, |x, y| Rgb(pixel(x, y).map(|v| (v * 65535.0).round() as u16)));
+        DynamicImage::ImageRgb16(result)
+    } else {
+        let result: RgbImage =
+            ImageBuffer::from_fn(width, height, |x, y| Rgb(pixel(x, y).map(|v| (v * 255.0).round() as u8)));
+        DynamicImage::ImageRgb8(result)
     }
+}
 
-    let result: RgbImage = ImageBuffer::from_fn(width, height, |x, y| {
-        let idx = (y * width + x) as usize;
-        Rgb(pixels[idx])
+/// Dark-channel-prior haze removal (He et al.) with the transmission map
+/// refined by a guided filter. Negative amounts blend toward the airlight.
+fn apply_dehaze(pixels: &mut [[f32; 3]], width: u32, height: u32, amount: f32) {
+    let (w, h) = (width as usize, height as usize);
+    let strength = (amount / 100.0).clamp(-1.0, 1.0);
+    let patch = ((w.max(h) as f32 * 0.01) as usize).max(1);
+
+    let dark: Vec<f32> = pixels
+        .par_iter()
+        .map(|p| p[0].min(p[1]).min(p[2]).clamp(0.0, 255.0) / 255.0)
+        .collect();
+    let dark = min_filter(&dark, w, h, patch);
+
+    // Airlight: average color of the haziest 0.1% of the frame
+    let count = (dark.len() / 1000).max(1);
+    let mut order: Vec<usize> = (0..dark.len()).collect();
+    order.select_nth_unstable_by(count - 1, |&a, &b| dark[b].total_cmp(&dark[a]));
+    let mut airlight = [0.0f32; 3];
+    for &i in &order[..count] {
+        for c in 0..3 {
+            airlight[c] += pixels[i][c].min(255.0) / 255.0 / count as f32;
+        }
+    }
+    let airlight = airlight.map(|a| a.max(0.05));
+
+    let normalized: Vec<f32> = pixels
+        .par_iter()
+        .map(|p| {
+            (0..3)
+                .map(|c| p[c].clamp(0.0, 255.0) / 255.0 / airlight[c])
+                .fold(f32::MAX, f32::min)
+        })
+        .collect();
+    let haze = min_filter(&normalized, w, h, patch);
+
+    let guide: Vec<f32> = luma_plane(pixels).iter().map(|l| l / 255.0).collect();
+    let haze = guided_filter(&guide, &haze, w, h, patch * 4, 1e-3);
+
+    pixels.par_iter_mut().zip(haze.par_iter()).for_each(|(pixel, &haze)| {
+        let haze = haze.clamp(0.0, 1.0);
+        for c in 0..3 {
+            let v = pixel[c] / 255.0;
+            let out = if strength > 0.0 {
+                let t = (1.0 - 0.95 * strength * haze).max(0.1);
+                (v - airlight[c]) / t + airlight[c]
+            } else {
+                let k = -strength * 0.6 * (1.0 - haze * 0.5);
+                v + (airlight[c] - v) * k
+            };
+            pixel[c] = (out * 255.0).max(0.0);
+        }
     });
+}
 
-    DynamicImage::ImageRgb8(result)
+/// Maps luminance above a knee onto the range up to white with a rational
+/// roll-off (slope 1 at the knee), so headroom far above white still lands
+/// just under it. `amount` 0 to 1 lowers the knee from white to mid-gray.
+fn recover_highlights(lum: f32, amount: f32) -> f32 {
+    let knee = 255.0 * (1.0 - 0.5 * amount);
+    if lum <= knee || knee >= 255.0 {
+        return lum;
+    }
+    let over = lum - knee;
+    let range = 255.0 - knee;
+    knee + over / (1.0 + over / range)
 }
 
-fn apply_sharpening_parallel(pixels: &[[u8; 3]], width: u32, height: u32, amount: f32) -> Vec<[u8; 3]> {
-    let factor = amount / 100.0;
-    let w = width as usize;
-    let h = height as usize;
+/// Clarity boosts large-radius local contrast in the midtones; texture boosts
+/// the finer band of detail above noise level.
+fn apply_local_contrast(pixels: &mut [[f32; 3]], width: u32, height: u32, clarity: f32, texture: f32) {
+    let (w, h) = (width as usize, height as usize);
+    let long_edge = w.max(h) as f32;
 
-    (0..pixels.len())
-        .into_par_iter()
-        .map(|idx| {
-            let x = idx % w;
-            let y = idx / w;
+    let clarity_band = (clarity != 0.0).then(|| clarity_detail(pixels, w, h));
+    let texture_band = (texture != 0.0).then(|| {
+        let luma = luma_plane(pixels);
+        detail_band(&luma, w, h, (long_edge * 0.0015).max(0.8), (long_edge * 0.005).max(2.0))
+    });
+    let clarity = clarity.clamp(-100.0, 100.0) / 100.0;
+    let texture = texture.clamp(-100.0, 100.0) / 100.0;
 
-            if x == 0 || y == 0 || x == w - 1 || y == h - 1 {
-                return pixels[idx];
-            }
+    pixels.par_iter_mut().enumerate().for_each(|(idx, pixel)| {
+        let lum = (0.299 * pixel[0] + 0.587 * pixel[1] + 0.114 * pixel[2]) / 255.0;
+        let midtones = (1.0 - (2.0 * lum - 1.0).powi(2)).max(0.0);
 
-            let center = &pixels[idx];
-            let top = &pixels[idx - w];
-            let bottom = &pixels[idx + w];
-            let left = &pixels[idx - 1];
-            let right = &pixels[idx + 1];
-
-            [
-                (center[0] as f32 + factor * (4.0 * center[0] as f32 - top[0] as f32 - bottom[0] as f32 - left[0] as f32 - right[0] as f32)).clamp(0.0, 255.0) as u8,
-                (center[1] as f32 + factor * (4.0 * center[1] as f32 - top[1] as f32 - bottom[1] as f32 - left[1] as f32 - right[1] as f32)).clamp(0.0, 255.0) as u8,
-                (center[2] as f32 + factor * (4.0 * center[2] as f32 - top[2] as f32 - bottom[2] as f32 - left[2] as f32 - right[2] as f32)).clamp(0.0, 255.0) as u8,
-            ]
-        })
-        .collect()
+        let mut delta = 0.0;
+        if let Some(band) = &clarity_band {
+            delta += clarity * band[idx] * midtones;
+        }
+        if let Some(band) = &texture_band {
+            delta += texture * band[idx] * 1.5;
+        }
+
+        for v in pixel.iter_mut() {
+            *v = (*v + delta).clamp(0.0, 255.0);
+        }
+    });
 }
 
-fn apply_noise_reduction_parallel(pixels: &[[u8; 3]], width: u32, height: u32, amount: f32) -> Vec<[u8; 3]> {
-    let factor = (amount / 100.0).clamp(0.0, 1.0);
-    let w = width as usize;
-    let h = height as usize;
+/// Unsharp mask on luma. `sharpening_radius` is the Gaussian radius in
+/// source pixels; detail damps large differences to avoid halos, and edge
+/// masking limits sharpening to areas with strong gradients.
+fn apply_sharpening(pixels: &mut [[f32; 3]], width: u32, height: u32, edits: &EditState, scale: f32) {
+    let (w, h) = (width as usize, height as usize);
+    let amount = edits.sharpening_amount.clamp(0.0, 150.0) / 100.0;
+    let sigma = edits.sharpening_radius.clamp(0.5, 3.0) * scale;
+    let detail = edits.sharpening_detail.clamp(0.0, 100.0) / 100.0;
+    let masking = edits.sharpening_masking.clamp(0.0, 100.0) / 100.0;
+
+    let luma = luma_plane(pixels);
+    let blurred = gaussian_blur(&luma, w, h, sigma);
+
+    let edge_mask = (masking > 0.0).then(|| {
+        let smooth = gaussian_blur(&luma, w, h, (sigma * 2.0).max(1.0));
+        let threshold = masking * 12.0;
+        (0..w * h)
+            .into_par_iter()
+            .map(|idx| {
+                let (x, y) = (idx % w, idx / w);
+                let gx = smooth[y * w + (x + 1).min(w - 1)] - smooth[y * w + x.saturating_sub(1)];
+                let gy = smooth[(y + 1).min(h - 1) * w + x] - smooth[y.saturating_sub(1) * w + x];
+                let t = (((gx * gx + gy * gy).sqrt() - threshold) / 8.0).clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            })
+            .collect::<Vec<f32>>()
+    });
+
+    pixels.par_iter_mut().enumerate().for_each(|(idx, pixel)| {
+        let high_pass = luma[idx] - blurred[idx];
+        // Low detail compresses big differences (edges) more than small ones
+        let damped = high_pass / (1.0 + high_pass.abs() / 8.0);
+        let mut delta = amount * (damped + (high_pass - damped) * detail);
+        if let Some(mask) = &edge_mask {
+            delta *= mask[idx];
+        }
+        for v in pixel.iter_mut() {
+            *v = (*v + delta).clamp(0.0, 255.0);
+        }
+    });
+}
 
-    (0..pixels.len())
-        .into_par_iter()
-        .map(|idx| {
-            let x = idx % w;
-            let y = idx / w;
+/// Separate luminance and color noise reduction in YCbCr using wavelet
+/// thresholding. Color noise is blotchy and coarse, so chroma uses more
+/// levels than luma.
+fn apply_noise_reduction(pixels: &mut [[f32; 3]], width: u32, height: u32, edits: &EditState) {
+    let (w, h) = (width as usize, height as usize);
+    let luma_strength = edits.noise_reduction.clamp(0.0, 100.0) / 100.0;
+    let luma_detail = edits.noise_reduction_detail.clamp(0.0, 100.0) / 100.0;
+    let chroma_strength = edits.color_noise_reduction.clamp(0.0, 100.0) / 100.0;
+    let chroma_detail = edits.color_noise_detail.clamp(0.0, 100.0) / 100.0;
+
+    let mut y = Vec::with_capacity(pixels.len());
+    let mut cb = Vec::with_capacity(pixels.len());
+    let mut cr = Vec::with_capacity(pixels.len());
+    for p in pixels.iter() {
+        let [r, g, b] = *p;
+        let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+        y.push(luma);
+        cb.push((b - luma) * 0.564);
+        cr.push((r - luma) * 0.713);
+    }
 
-            if x == 0 || y == 0 || x == w - 1 || y == h - 1 {
-                return pixels[idx];
-            }
+    if luma_strength > 0.0 {
+        y = wavelet_denoise(&y, w, h, 4, luma_strength, luma_detail);
+    }
+    if chroma_strength > 0.0 {
+        // Chroma noise sits well above the sensor noise floor; push harder
+        cb = wavelet_denoise(&cb, w, h, 6, chroma_strength * 2.0, chroma_detail);
+        cr = wavelet_denoise(&cr, w, h, 6, chroma_strength * 2.0, chroma_detail);
+    }
 
-            let center = &pixels[idx];
-            let neighbors = [
-                &pixels[idx - w - 1], &pixels[idx - w], &pixels[idx - w + 1],
-                &pixels[idx - 1],                       &pixels[idx + 1],
-                &pixels[idx + w - 1], &pixels[idx + w], &pixels[idx + w + 1],
-            ];
-
-            let avg_r: f32 = neighbors.iter().map(|p| p[0] as f32).sum::<f32>() / 8.0;
-            let avg_g: f32 = neighbors.iter().map(|p| p[1] as f32).sum::<f32>() / 8.0;
-            let avg_b: f32 = neighbors.iter().map(|p| p[2] as f32).sum::<f32>() / 8.0;
-
-            [
-                (center[0] as f32 * (1.0 - factor) + avg_r * factor).clamp(0.0, 255.0) as u8,
-                (center[1] as f32 * (1.0 - factor) + avg_g * factor).clamp(0.0, 255.0) as u8,
-                (center[2] as f32 * (1.0 - factor) + avg_b * factor).clamp(0.0, 255.0) as u8,
-            ]
-        })
-        .collect()
+    pixels.par_iter_mut().enumerate().for_each(|(i, pixel)| {
+        let r = y[i] + 1.403 * cr[i];
+        let g = y[i] - 0.344 * cb[i] - 0.714 * cr[i];
+        let b = y[i] + 1.773 * cb[i];
+        *pixel = [r, g, b].map(|v| v.clamp(0.0, 255.0));
+    });
 }
 
 pub fn resize_to_fit(img: DynamicImage, max_size: u32) -> DynamicImage {
@@ -183,6 +424,40 @@ pub fn resize_to_fit(img: DynamicImage, max_size: u32) -> DynamicImage {
     img.resize(new_w, new_h, image::imageops::FilterType::Triangle)
 }
 
+/// Sharpening for the output size, after resizing: an unsharp mask on luma
+/// with a radius and strength that suit the medium. Prints lose more detail
+/// than screens, matte paper most of all.
+pub fn apply_output_sharpening(img: DynamicImage, sharpening: &OutputSharpening) -> DynamicImage {
+    let (sigma, base) = match sharpening.medium {
+        SharpenMedium::Screen => (0.6, 0.5),
+        SharpenMedium::Glossy => (0.9, 0.8),
+        SharpenMedium::Matte => (1.1, 1.1),
+    };
+    let amount = base
+        * match sharpening.amount {
+            SharpenAmount::Low => 0.6,
+            SharpenAmount::Standard => 1.0,
+            SharpenAmount::High => 1.5,
+        };
+
+    let (width, height) = (img.width(), img.height());
+    if width < 3 || height < 3 {
+        return img;
+    }
+    let mut pixels = to_pixels(&img);
+    let luma = luma_plane(&pixels);
+    let blurred = gaussian_blur(&luma, width as usize, height as usize, sigma);
+
+    pixels.par_iter_mut().enumerate().for_each(|(idx, pixel)| {
+        let delta = amount * (luma[idx] - blurred[idx]);
+        for v in pixel.iter_mut() {
+            *v = (*v + delta).clamp(0.0, 255.0);
+        }
+    });
+
+    from_pixels(width, height, &pixels, is_high_depth(&img))
+}
+
 pub fn apply_crop(img: DynamicImage, crop: &crate::models::CropRect) -> DynamicImage {
     let x = (crop.x.max(0.0) as u32).min(img.width().saturating_sub(1));
     let y = (crop.y.max(0.0) as u32).min(img.height().saturating_sub(1));
@@ -192,6 +467,44 @@ pub fn apply_crop(img: DynamicImage, crop: &crate::models::CropRect) -> DynamicI
     img.crop_imm(x, y, width, height)
 }
 
+/// Average linear sRGB of a normalized region, ignoring clipped pixels.
+pub fn average_linear_rgb(img: &DynamicImage, region: &SampleRegion) -> Option<[f32; 3]> {
+    let rgb = img.to_rgb8();
+    let (w, h) = rgb.dimensions();
+    if w == 0 || h == 0 {
+        return None;
+    }
+
+    let x0 = ((region.x.clamp(0.0, 1.0) * w as f32) as u32).min(w - 1);
+    let y0 = ((region.y.clamp(0.0, 1.0) * h as f32) as u32).min(h - 1);
+    let x1 = (((region.x + region.width).clamp(0.0, 1.0) * w as f32).ceil() as u32).clamp(x0 + 1, w);
+    let y1 = (((region.y + region.height).clamp(0.0, 1.0) * h as f32).ceil() as u32).clamp(y0 + 1, h);
+
+    let mut sum = [0.0f64; 3];
+    let mut count = 0u32;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let p = rgb.get_pixel(x, y);
+            if p.0.iter().any(|&c| c >= 250) || p.0.iter().all(|&c| c <= 5) {
+                continue;
+            }
+            for c in 0..3 {
+                sum[c] += white_balance::srgb_to_linear(p[c] as f32 / 255.0) as f64;
+            }
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        return None;
+    }
+    Some([
+        (sum[0] / count as f64) as f32,
+        (sum[1] / count as f64) as f32,
+        (sum[2] / count as f64) as f32,
+    ])
+}
+
 pub fn rotate_image(img: DynamicImage, degrees: u16) -> DynamicImage {
     match degrees {
         90 => img.rotate90(),
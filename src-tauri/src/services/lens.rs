@@ -0,0 +1,541 @@
+use image::{DynamicImage, Rgb32FImage};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use rayon::prelude::*;
+use std::fs;
+use std::sync::OnceLock;
+
+use crate::models::{LensCorrection, LensInfo};
+use crate::services::exif::read_exif;
+use crate::services::filesystem::get_lens_profile_dir;
+use crate::services::white_balance::{linear_to_srgb, srgb_to_linear};
+
+/// Distortion models from the lensfun database. Each maps an undistorted
+/// radius `ru` to the distorted radius `rd` where the source pixel lives.
+#[derive(Debug, Clone, Copy)]
+pub enum DistortionModel {
+    /// rd = ru * (1 - k1 + k1 * ru^2)
+    Poly3 { k1: f32 },
+    /// rd = ru * (1 + k1 * ru^2 + k2 * ru^4)
+    Poly5 { k1: f32, k2: f32 },
+    /// rd = ru * (a * ru^3 + b * ru^2 + c * ru + 1 - a - b - c)
+    PtLens { a: f32, b: f32, c: f32 },
+}
+
+impl DistortionModel {
+    fn ratio(&self, ru: f32) -> f32 {
+        let r2 = ru * ru;
+        match *self {
+            Self::Poly3 { k1 } => 1.0 - k1 + k1 * r2,
+            Self::Poly5 { k1, k2 } => 1.0 + k1 * r2 + k2 * r2 * r2,
+            Self::PtLens { a, b, c } => a * r2 * ru + b * r2 + c * ru + 1.0 - a - b - c,
+        }
+    }
+
+    fn coefficients(&self) -> [f32; 3] {
+        match *self {
+            Self::Poly3 { k1 } => [k1, 0.0, 0.0],
+            Self::Poly5 { k1, k2 } => [k1, k2, 0.0],
+            Self::PtLens { a, b, c } => [a, b, c],
+        }
+    }
+
+    fn with_coefficients(&self, k: [f32; 3]) -> Self {
+        match self {
+            Self::Poly3 { .. } => Self::Poly3 { k1: k[0] },
+            Self::Poly5 { .. } => Self::Poly5 { k1: k[0], k2: k[1] },
+            Self::PtLens { .. } => Self::PtLens { a: k[0], b: k[1], c: k[2] },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DistortionCalibration {
+    focal: f32,
+    model: DistortionModel,
+}
+
+/// Lateral CA as poly3 terms per channel: rd = ru * (b * ru^2 + c * ru + v).
+#[derive(Debug, Clone)]
+struct TcaCalibration {
+    focal: f32,
+    red: [f32; 3],
+    blue: [f32; 3],
+}
+
+/// Pablo d'Angelo vignetting: gain = 1 + k1 * r^2 + k2 * r^4 + k3 * r^6.
+#[derive(Debug, Clone)]
+struct VignettingCalibration {
+    focal: f32,
+    aperture: f32,
+    distance: f32,
+    k: [f32; 3],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LensProfile {
+    pub maker: String,
+    pub model: String,
+    distortion: Vec<DistortionCalibration>,
+    tca: Vec<TcaCalibration>,
+    vignetting: Vec<VignettingCalibration>,
+}
+
+/// Calibration of one lens interpolated for a particular shot.
+#[derive(Debug, Clone, Default)]
+pub struct ShotCalibration {
+    pub profile_name: String,
+    distortion: Option<DistortionModel>,
+    tca: Option<([f32; 3], [f32; 3])>,
+    vignetting: Option<[f32; 3]>,
+}
+
+#[derive(Debug, Default)]
+pub struct LensDatabase {
+    pub lenses: Vec<LensProfile>,
+}
+
+static DATABASE: OnceLock<LensDatabase> = OnceLock::new();
+
+/// Lens profiles from every lensfun XML file in the profile directory,
+/// loaded once on first use.
+pub fn database() -> &'static LensDatabase {
+    DATABASE.get_or_init(|| {
+        let mut db = LensDatabase::default();
+        let dir = get_lens_profile_dir();
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return db;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("xml")) {
+                match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|c| parse_lensfun(&c)) {
+                    Ok(mut lenses) => db.lenses.append(&mut lenses),
+                    Err(e) => tracing::warn!("Skipping lens database {}: {}", path.display(), e),
+                }
+            }
+        }
+        tracing::info!("Loaded {} lens profiles from {}", db.lenses.len(), dir.display());
+        db
+    })
+}
+
+/// Parses the `<lens>` entries of a lensfun database file.
+pub fn parse_lensfun(content: &str) -> Result<Vec<LensProfile>, String> {
+    let mut reader = Reader::from_str(content);
+    reader.trim_text(true);
+
+    let mut lenses = Vec::new();
+    let mut current: Option<LensProfile> = None;
+    let mut field: Option<String> = None;
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                if name == "lens" {
+                    current = Some(LensProfile::default());
+                } else if current.is_some() && (name == "maker" || name == "model") {
+                    // Only the untranslated name (no lang attribute)
+                    let localized = e.attributes().filter_map(|a| a.ok()).any(|a| a.key.as_ref() == b"lang");
+                    field = (!localized).then_some(name);
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                if let Some(lens) = current.as_mut() {
+                    parse_calibration(&e, lens);
+                }
+            }
+            Ok(Event::Text(t)) => {
+                if let (Some(lens), Some(name)) = (current.as_mut(), field.take()) {
+                    let text = t.unescape().map_err(|e| e.to_string())?.to_string();
+                    match name.as_str() {
+                        "maker" => lens.maker = text,
+                        _ => lens.model = text,
+                    }
+                }
+            }
+            Ok(Event::End(e)) => {
+                field = None;
+                if e.name().as_ref() == b"lens" {
+                    if let Some(lens) = current.take() {
+                        if !lens.model.is_empty() {
+                            lenses.push(lens);
+                        }
+                    }
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(format!("XML parse error: {}", e)),
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(lenses)
+}
+
+fn parse_calibration(e: &BytesStart, lens: &mut LensProfile) {
+    let attrs: Vec<(String, String)> = e
+        .attributes()
+        .filter_map(|a| a.ok())
+        .map(|a| {
+            (
+                String::from_utf8_lossy(a.key.as_ref()).to_string(),
+                String::from_utf8_lossy(&a.value).to_string(),
+            )
+        })
+        .collect();
+    let get = |key: &str| -> f32 {
+        attrs
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.parse().ok())
+            .unwrap_or(0.0)
+    };
+    let model = attrs.iter().find(|(k, _)| k == "model").map(|(_, v)| v.as_str());
+    let focal = get("focal");
+
+    match (e.name().as_ref(), model) {
+        (b"distortion", Some("poly3")) => lens.distortion.push(DistortionCalibration {
+            focal,
+            model: DistortionModel::Poly3 { k1: get("k1") },
+        }),
+        (b"distortion", Some("poly5")) => lens.distortion.push(DistortionCalibration {
+            focal,
+            model: DistortionModel::Poly5 { k1: get("k1"), k2: get("k2") },
+        }),
+        (b"distortion", Some("ptlens")) => lens.distortion.push(DistortionCalibration {
+            focal,
+            model: DistortionModel::PtLens { a: get("a"), b: get("b"), c: get("c") },
+        }),
+        (b"tca", Some("linear")) => lens.tca.push(TcaCalibration {
+            focal,
+            red: [0.0, 0.0, get("kr")],
+            blue: [0.0, 0.0, get("kb")],
+        }),
+        (b"tca", Some("poly3")) => {
+            let or_one = |v: f32| if v == 0.0 { 1.0 } else { v };
+            lens.tca.push(TcaCalibration {
+                focal,
+                red: [get("br"), get("cr"), or_one(get("vr"))],
+                blue: [get("bb"), get("cb"), or_one(get("vb"))],
+            })
+        }
+        (b"vignetting", Some("pa")) => lens.vignetting.push(VignettingCalibration {
+            focal,
+            aperture: get("aperture"),
+            distance: get("distance"),
+            k: [get("k1"), get("k2"), get("k3")],
+        }),
+        _ => {}
+    }
+}
+
+/// Reduces a model name to comparable lowercase tokens, splitting letters
+/// from numbers so "EF24-70mm" and "EF 24-70 mm" agree.
+fn tokens(name: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut numeric = false;
+    for c in name.to_lowercase().chars() {
+        let is_number = c.is_ascii_digit() || (c == '.' && numeric);
+        if !c.is_alphanumeric() && !is_number {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if !current.is_empty() && is_number != numeric {
+            tokens.push(std::mem::take(&mut current));
+        }
+        numeric = is_number;
+        current.push(c);
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn is_number(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_digit())
+}
+
+impl LensDatabase {
+    pub fn find_by_name(&self, name: &str) -> Option<&LensProfile> {
+        self.lenses.iter().find(|l| l.model == name)
+    }
+
+    /// Best profile for the lens name reported in EXIF. Focal lengths and
+    /// apertures in the names must agree exactly; most of the remaining
+    /// words of the profile name must appear in the EXIF name.
+    pub fn match_lens(&self, exif_lens: &str) -> Option<&LensProfile> {
+        let wanted = tokens(exif_lens);
+        if wanted.is_empty() {
+            return None;
+        }
+        let mut wanted_numbers: Vec<&String> = wanted.iter().filter(|t| is_number(t)).collect();
+        wanted_numbers.sort();
+
+        self.lenses
+            .iter()
+            .filter_map(|lens| {
+                // EXIF lens names often omit the maker
+                let maker = tokens(&lens.maker);
+                let have: Vec<String> = tokens(&lens.model)
+                    .into_iter()
+                    .filter(|t| !maker.contains(t))
+                    .collect();
+                let mut numbers: Vec<&String> = have.iter().filter(|t| is_number(t)).collect();
+                numbers.sort();
+                if have.is_empty() || numbers != wanted_numbers {
+                    return None;
+                }
+                let common = have.iter().filter(|t| wanted.contains(t)).count();
+                let score = common as f32 / have.len() as f32;
+                (score >= 0.7).then_some((score, lens))
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, lens)| lens)
+    }
+}
+
+impl LensProfile {
+    /// Interpolates calibration data for a focal length and aperture.
+    pub fn calibrate(&self, focal: f32, aperture: Option<f32>) -> ShotCalibration {
+        let distortion = interpolate_by_focal(&self.distortion, focal, |c| c.focal, |c| c.model.coefficients())
+            .and_then(|(k, nearest)| {
+                let model = self.distortion.iter().find(|c| c.focal == nearest)?.model;
+                Some(model.with_coefficients(k))
+            });
+
+        let tca = interpolate_by_focal(&self.tca, focal, |c| c.focal, |c| c.red)
+            .zip(interpolate_by_focal(&self.tca, focal, |c| c.focal, |c| c.blue))
+            .map(|((red, _), (blue, _))| (red, blue));
+
+        // Vignetting also depends on aperture; use entries at the nearest
+        // focal length and aperture, taken at the farthest distance.
+        let vignetting = self
+            .vignetting
+            .iter()
+            .min_by(|a, b| {
+                let key = |c: &VignettingCalibration| {
+                    let aperture_gap = aperture.map_or(0.0, |f| (c.aperture - f).abs());
+                    ((c.focal - focal).abs(), aperture_gap, -c.distance)
+                };
+                let (ka, kb) = (key(a), key(b));
+                ka.0.total_cmp(&kb.0)
+                    .then(ka.1.total_cmp(&kb.1))
+                    .then(ka.2.total_cmp(&kb.2))
+            })
+            .map(|c| c.k);
+
+        ShotCalibration {
+            profile_name: self.model.clone(),
+            distortion,
+            tca,
+            vignetting,
+        }
+    }
+}
+
+/// Linear interpolation of coefficients between the calibrations bracketing
+/// `focal`; returns the coefficients and the focal length of the nearest entry.
+fn interpolate_by_focal<T, F, K>(items: &[T], focal: f32, focal_of: F, coefficients: K) -> Option<([f32; 3], f32)>
+where
+    F: Fn(&T) -> f32,
+    K: Fn(&T) -> [f32; 3],
+{
+    let below = items
+        .iter()
+        .filter(|c| focal_of(c) <= focal)
+        .max_by(|a, b| focal_of(a).total_cmp(&focal_of(b)));
+    let above = items
+        .iter()
+        .filter(|c| focal_of(c) >= focal)
+        .min_by(|a, b| focal_of(a).total_cmp(&focal_of(b)));
+
+    match (below, above) {
+        (Some(lo), Some(hi)) if focal_of(hi) > focal_of(lo) => {
+            let t = (focal - focal_of(lo)) / (focal_of(hi) - focal_of(lo));
+            let (a, b) = (coefficients(lo), coefficients(hi));
+            let nearest = if t < 0.5 { focal_of(lo) } else { focal_of(hi) };
+            Some(([0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t), nearest))
+        }
+        (Some(c), _) | (None, Some(c)) => Some((coefficients(c), focal_of(c))),
+        _ => None,
+    }
+}
+
+/// Reads camera and lens details from EXIF and finds the matching profile.
+pub fn detect_lens(image_path: &str, profile_name: Option<&str>) -> LensInfo {
+    let exif = read_exif(image_path);
+    let lens_model = exif.as_ref().and_then(|e| e.lens_model());
+    let db = database();
+    // Names that aren't lensfun models (e.g. Lightroom's profile names) fall
+    // back to matching the EXIF lens
+    let profile = profile_name
+        .and_then(|name| db.find_by_name(name))
+        .or_else(|| lens_model.as_deref().and_then(|m| db.match_lens(m)));
+
+    LensInfo {
+        camera_make: exif.as_ref().and_then(|e| e.make()),
+        camera_model: exif.as_ref().and_then(|e| e.model()),
+        lens_model,
+        focal_length: exif.as_ref().and_then(|e| e.focal_length()),
+        aperture: exif.as_ref().and_then(|e| e.f_number()),
+        profile_name: profile.map(|p| p.model.clone()),
+    }
+}
+
+/// Profile calibration for a shot, when profile correction is enabled and a
+/// profile is found for the lens.
+pub fn shot_calibration(image_path: &str, settings: &LensCorrection) -> Option<ShotCalibration> {
+    if !settings.profile_enabled {
+        return None;
+    }
+    let exif = read_exif(image_path);
+    let db = database();
+    let profile = settings
+        .profile_name
+        .as_deref()
+        .and_then(|name| db.find_by_name(name))
+        .or_else(|| exif.as_ref().and_then(|e| e.lens_model()).and_then(|m| db.match_lens(&m)))?;
+
+    let focal = exif.as_ref().and_then(|e| e.focal_length()).unwrap_or(0.0);
+    let aperture = exif.as_ref().and_then(|e| e.f_number());
+    Some(profile.calibrate(focal, aperture))
+}
+
+/// Lens corrections prepared for one render: profile data scaled by the
+/// user's amounts, combined with the manual sliders.
+pub struct LensCorrector {
+    distortion: Option<DistortionModel>,
+    manual_distortion: f32,
+    red_scale: [f32; 3],
+    blue_scale: [f32; 3],
+    vignetting: [f32; 3],
+    manual_vignetting: f32,
+}
+
+impl LensCorrector {
+    /// Returns `None` when nothing would change.
+    pub fn new(settings: &LensCorrection, calibration: Option<&ShotCalibration>) -> Option<Self> {
+        let distortion_scale = settings.distortion_scale.clamp(0.0, 200.0) / 100.0;
+        let vignetting_scale = settings.vignetting_scale.clamp(0.0, 200.0) / 100.0;
+
+        let distortion = calibration
+            .and_then(|c| c.distortion)
+            .map(|m| m.with_coefficients(m.coefficients().map(|k| k * distortion_scale)));
+
+        let (mut red_scale, mut blue_scale) = calibration
+            .and_then(|c| c.tca)
+            .unwrap_or(([0.0, 0.0, 1.0], [0.0, 0.0, 1.0]));
+        red_scale[2] *= 1.0 + settings.ca_red.clamp(-100.0, 100.0) / 100.0 * 0.003;
+        blue_scale[2] *= 1.0 + settings.ca_blue.clamp(-100.0, 100.0) / 100.0 * 0.003;
+
+        let vignetting = calibration
+            .and_then(|c| c.vignetting)
+            .map(|k| k.map(|v| v * vignetting_scale))
+            .unwrap_or([0.0; 3]);
+
+        let corrector = Self {
+            distortion,
+            // Positive removes barrel distortion
+            manual_distortion: -settings.distortion.clamp(-100.0, 100.0) / 100.0 * 0.1,
+            red_scale,
+            blue_scale,
+            vignetting,
+            manual_vignetting: settings.vignetting.clamp(-100.0, 100.0) / 100.0,
+        };
+
+        let identity = corrector.distortion.is_none()
+            && corrector.manual_distortion == 0.0
+            && corrector.red_scale == [0.0, 0.0, 1.0]
+            && corrector.blue_scale == [0.0, 0.0, 1.0]
+            && corrector.vignetting == [0.0; 3]
+            && corrector.manual_vignetting == 0.0;
+        (!identity).then_some(corrector)
+    }
+
+    pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+        let src = img.to_rgb32f();
+        let (w, h) = src.dimensions();
+        if w < 2 || h < 2 {
+            return DynamicImage::ImageRgb32F(src);
+        }
+
+        let cx = (w as f32 - 1.0) / 2.0;
+        let cy = (h as f32 - 1.0) / 2.0;
+        // lensfun normalizes geometry to half the short side and vignetting
+        // to half the diagonal
+        let geometry_norm = w.min(h) as f32 / 2.0;
+        let diagonal_norm = (w as f32).hypot(h as f32) / 2.0;
+        let data = src.as_raw();
+
+        let mut out = vec![0.0f32; data.len()];
+        out.par_chunks_mut(w as usize * 3).enumerate().for_each(|(y, row)| {
+            for x in 0..w as usize {
+                let u = (x as f32 - cx) / geometry_norm;
+                let v = (y as f32 - cy) / geometry_norm;
+                let ru = (u * u + v * v).sqrt();
+
+                let mut ratio = 1.0 + self.manual_distortion * ru * ru;
+                if let Some(model) = &self.distortion {
+                    ratio *= model.ratio(ru);
+                }
+                let rd = ru * ratio;
+
+                let channel_ratio = [tca_ratio(&self.red_scale, rd), 1.0, tca_ratio(&self.blue_scale, rd)];
+                let px = x * 3;
+                for c in 0..3 {
+                    let k = ratio * channel_ratio[c];
+                    let sx = cx + u * k * geometry_norm;
+                    let sy = cy + v * k * geometry_norm;
+                    row[px + c] = sample(data, w, h, sx, sy, c);
+                }
+
+                let sx = cx + u * ratio * geometry_norm;
+                let sy = cy + v * ratio * geometry_norm;
+                let rv2 = ((sx - cx).powi(2) + (sy - cy).powi(2)) / (diagonal_norm * diagonal_norm);
+                let falloff = 1.0
+                    + self.vignetting[0] * rv2
+                    + self.vignetting[1] * rv2 * rv2
+                    + self.vignetting[2] * rv2 * rv2 * rv2;
+                let gain = (1.0 + self.manual_vignetting * rv2) / falloff.max(0.05);
+                if gain != 1.0 {
+                    for c in 0..3 {
+                        let value = row[px + c];
+                        let linear = srgb_to_linear(value.max(0.0)) * gain;
+                        row[px + c] = linear_to_srgb(linear);
+                    }
+                }
+            }
+        });
+
+        let buffer = Rgb32FImage::from_raw(w, h, out).expect("buffer matches dimensions");
+        DynamicImage::ImageRgb32F(buffer)
+    }
+}
+
+fn tca_ratio(k: &[f32; 3], r: f32) -> f32 {
+    k[0] * r * r + k[1] * r + k[2]
+}
+
+/// Bilinear sample of one channel, clamped to the image edge.
+fn sample(data: &[f32], w: u32, h: u32, x: f32, y: f32, c: usize) -> f32 {
+    let x = x.clamp(0.0, (w - 1) as f32);
+    let y = y.clamp(0.0, (h - 1) as f32);
+    let x0 = x.floor() as usize;
+    let y0 = y.floor() as usize;
+    let x1 = (x0 + 1).min(w as usize - 1);
+    let y1 = (y0 + 1).min(h as usize - 1);
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let at = |xx: usize, yy: usize| data[(yy * w as usize + xx) * 3 + c];
+
+    let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * fx;
+    let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * fx;
+    top + (bottom - top) * fy
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::models::{LookInfo, LutInterpolation};
+use crate::services::filesystem::get_looks_dir;
+
+pub const LOOK_EXTENSIONS: &[&str] = &["cube", "png"];
+
+/// A 3D color lookup table. Entries are stored red-fastest, as in `.cube`.
+#[derive(Debug, Clone)]
+pub struct Lut3d {
+    pub size: usize,
+    data: Vec<[f32; 3]>,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+}
+
+impl Lut3d {
+    fn at(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.data[r + g * self.size + b * self.size * self.size]
+    }
+
+    /// Looks up a color given on the 0 to 1 scale.
+    pub fn sample(&self, rgb: [f32; 3], interpolation: LutInterpolation) -> [f32; 3] {
+        let max = (self.size - 1) as f32;
+        let pos = [0, 1, 2].map(|c| {
+            let range = (self.domain_max[c] - self.domain_min[c]).max(1e-6);
+            ((rgb[c] - self.domain_min[c]) / range).clamp(0.0, 1.0) * max
+        });
+        let base = pos.map(|p| (p.floor() as usize).min(self.size - 2));
+        let f = [0, 1, 2].map(|c| pos[c] - base[c] as f32);
+        let corner = |dr: usize, dg: usize, db: usize| self.at(base[0] + dr, base[1] + dg, base[2] + db);
+
+        match interpolation {
+            LutInterpolation::Trilinear => {
+                let lerp = |a: [f32; 3], b: [f32; 3], t: f32| [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * t);
+                let c00 = lerp(corner(0, 0, 0), corner(1, 0, 0), f[0]);
+                let c10 = lerp(corner(0, 1, 0), corner(1, 1, 0), f[0]);
+                let c01 = lerp(corner(0, 0, 1), corner(1, 0, 1), f[0]);
+                let c11 = lerp(corner(0, 1, 1), corner(1, 1, 1), f[0]);
+                lerp(lerp(c00, c10, f[1]), lerp(c01, c11, f[1]), f[2])
+            }
+            LutInterpolation::Tetrahedral => {
+                let (fr, fg, fb) = (f[0], f[1], f[2]);
+                let c000 = corner(0, 0, 0);
+                let c111 = corner(1, 1, 1);
+                // Pick the tetrahedron containing the point by ordering the
+                // fractional parts; weights are the differences between them.
+                let (a, b, wa, wb, w0, w1) = if fr > fg {
+                    if fg > fb {
+                        (corner(1, 0, 0), corner(1, 1, 0), fr - fg, fg - fb, 1.0 - fr, fb)
+                    } else if fr > fb {
+                        (corner(1, 0, 0), corner(1, 0, 1), fr - fb, fb - fg, 1.0 - fr, fg)
+                    } else {
+                        (corner(0, 0, 1), corner(1, 0, 1), fb - fr, fr - fg, 1.0 - fb, fg)
+                    }
+                } else if fb > fg {
+                    (corner(0, 0, 1), corner(0, 1, 1), fb - fg, fg - fr, 1.0 - fb, fr)
+                } else if fb > fr {
+                    (corner(0, 1, 0), corner(0, 1, 1), fg - fb, fb - fr, 1.0 - fg, fr)
+                } else {
+                    (corner(0, 1, 0), corner(1, 1, 0), fg - fr, fr - fb, 1.0 - fg, fb)
+                };
+                [0, 1, 2].map(|c| w0 * c000[c] + wa * a[c] + wb * b[c] + w1 * c111[c])
+            }
+        }
+    }
+}
+
+/// Parses an Adobe/Resolve `.cube` 3D LUT.
+pub fn parse_cube(content: &str) -> Result<Lut3d, String> {
+    let mut size = None;
+    let mut domain_min = [0.0; 3];
+    let mut domain_max = [1.0; 3];
+    let mut data = Vec::new();
+
+    let parse_triple = |parts: &[&str]| -> Result<[f32; 3], String> {
+        if parts.len() != 3 {
+            return Err(format!("Expected 3 values, found {}", parts.len()));
+        }
+        let mut out = [0.0; 3];
+        for (o, p) in out.iter_mut().zip(parts) {
+            *o = p.parse().map_err(|_| format!("Invalid number: {}", p))?;
+        }
+        Ok(out)
+    };
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts[0] {
+            "TITLE" => {}
+            "LUT_3D_SIZE" => {
+                let n: usize = parts
+                    .get(1)
+                    .and_then(|v| v.parse().ok())
+                    .ok_or("Invalid LUT_3D_SIZE")?;
+                if !(2..=256).contains(&n) {
+                    return Err(format!("Unsupported LUT size: {}", n));
+                }
+                size = Some(n);
+            }
+            "LUT_1D_SIZE" => return Err("1D LUTs are not supported".to_string()),
+            "DOMAIN_MIN" => domain_min = parse_triple(&parts[1..])?,
+            "DOMAIN_MAX" => domain_max = parse_triple(&parts[1..])?,
+            keyword if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                // Unknown keywords (e.g. LUT_IN_VIDEO_RANGE) are ignored
+            }
+            _ => data.push(parse_triple(&parts)?),
+        }
+    }
+
+    let size = size.ok_or("Missing LUT_3D_SIZE")?;
+    if data.len() != size * size * size {
+        return Err(format!("Expected {} entries, found {}", size * size * size, data.len()));
+    }
+    Ok(Lut3d { size, data, domain_min, domain_max })
+}
+
+/// Loads a HaldCLUT image: a square of level^3 pixels holding a level^2 cube.
+pub fn load_hald(path: &Path) -> Result<Lut3d, String> {
+    let img = image::open(path)
+        .map_err(|e| format!("Failed to open HaldCLUT: {}", e))?
+        .to_rgb32f();
+    let (width, height) = img.dimensions();
+    let level = (width as f32).cbrt().round() as u32;
+    if width != height || level * level * level != width || level < 2 {
+        return Err(format!("Not a HaldCLUT image: {}x{}", width, height));
+    }
+
+    let size = (level * level) as usize;
+    let data = img.pixels().map(|p| p.0).collect();
+    Ok(Lut3d {
+        size,
+        data,
+        domain_min: [0.0; 3],
+        domain_max: [1.0; 3],
+    })
+}
+
+/// Path of a look in the looks directory. Names are plain file names so a
+/// sidecar cannot point outside the directory.
+pub fn look_path(name: &str) -> Result<PathBuf, String> {
+    let file_name = Path::new(name).file_name().map(|n| n.to_string_lossy().to_string());
+    if file_name.as_deref() != Some(name) {
+        return Err(format!("Invalid look name: {}", name));
+    }
+    Ok(get_looks_dir().join(name))
+}
+
+fn read_look(path: &Path) -> Result<Lut3d, String> {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "cube" => {
+            let content = fs::read_to_string(path).map_err(|e| format!("Read failed: {}", e))?;
+            parse_cube(&content)
+        }
+        "png" => load_hald(path),
+        _ => Err(format!("Unsupported look format: {}", ext)),
+    }
+}
+
+static LOOK_CACHE: OnceLock<Mutex<HashMap<String, Arc<Lut3d>>>> = OnceLock::new();
+
+/// Loads a look by name, parsing each file once.
+pub fn load_look(name: &str) -> Result<Arc<Lut3d>, String> {
+    let cache = LOOK_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(lut) = cache.lock().unwrap().get(name) {
+        return Ok(lut.clone());
+    }
+    let lut = Arc::new(read_look(&look_path(name)?)?);
+    cache.lock().unwrap().insert(name.to_string(), lut.clone());
+    Ok(lut)
+}
+
+fn forget_look(name: &str) {
+    if let Some(cache) = LOOK_CACHE.get() {
+        cache.lock().unwrap().remove(name);
+    }
+}
+
+/// Looks in the looks directory that parse, sorted by name.
+pub fn list_looks() -> Vec<LookInfo> {
+    let Ok(entries) = fs::read_dir(get_looks_dir()) else {
+        return Vec::new();
+    };
+    let mut looks: Vec<LookInfo> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let ext = Path::new(&name).extension()?.to_string_lossy().to_lowercase();
+            if !LOOK_EXTENSIONS.contains(&ext.as_str()) {
+                return None;
+            }
+            match load_look(&name) {
+                Ok(lut) => Some(LookInfo { name, size: lut.size as u32 }),
+                Err(e) => {
+                    tracing::warn!("Skipping look {}: {}", name, e);
+                    None
+                }
+            }
+        })
+        .collect();
+    looks.sort_by_key(|l| l.name.to_lowercase());
+    looks
+}
+
+/// Validates a LUT file and copies it into the looks directory.
+pub fn import_look(source: &Path) -> Result<LookInfo, String> {
+    let lut = read_look(source)?;
+    let name = source
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or("Invalid file name")?;
+
+    let dir = get_looks_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Create dir failed: {}", e))?;
+    fs::copy(source, dir.join(&name)).map_err(|e| format!("Copy failed: {}", e))?;
+    forget_look(&name);
+    Ok(LookInfo { name, size: lut.size as u32 })
+}
+
+pub fn delete_look(name: &str) -> Result<(), String> {
+    let path = look_path(name)?;
+    fs::remove_file(&path).map_err(|e| format!("Delete failed: {}", e))?;
+    forget_look(name);
+    Ok(())
+}
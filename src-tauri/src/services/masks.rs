@@ -0,0 +1,246 @@
+use rayon::prelude::*;
+
+use crate::models::{BrushStroke, LocalAdjustment, Mask, MaskShape};
+use crate::services::filters::clarity_detail;
+use crate::services::white_balance;
+
+/// Applies each mask's local adjustment, blended by the mask's coverage.
+pub fn apply_masks(pixels: &mut [[f32; 3]], width: u32, height: u32, masks: &[Mask]) {
+    let (w, h) = (width as usize, height as usize);
+    if masks.is_empty() || w == 0 || h == 0 {
+        return;
+    }
+
+    // Clarity detail is taken from the image before any mask is applied and
+    // shared by every mask that needs it.
+    let detail = masks
+        .iter()
+        .any(|m| m.adjustment.clarity != 0.0)
+        .then(|| clarity_detail(pixels, w, h));
+
+    for mask in masks {
+        if mask.adjustment == LocalAdjustment::default() {
+            continue;
+        }
+
+        let alpha = render_mask(mask, w, h);
+        let local = LocalOps::new(&mask.adjustment);
+
+        pixels
+            .par_iter_mut()
+            .zip(alpha.par_iter())
+            .enumerate()
+            .for_each(|(idx, (pixel, &a))| {
+                if a <= 0.0 {
+                    return;
+                }
+                let d = detail.as_ref().map_or(0.0, |d| d[idx]);
+                let adjusted = local.apply(*pixel, d);
+                for c in 0..3 {
+                    let v = pixel[c] + (adjusted[c] - pixel[c]) * a;
+                    pixel[c] = v.clamp(0.0, 255.0);
+                }
+            });
+    }
+}
+
+/// Per-pixel coverage of a mask, 0.0 to 1.0.
+pub fn render_mask(mask: &Mask, w: usize, h: usize) -> Vec<f32> {
+    let mut alpha = match &mask.shape {
+        MaskShape::Linear { start_x, start_y, end_x, end_y } => {
+            let (sx, sy) = (start_x * w as f32, start_y * h as f32);
+            let (dx, dy) = (end_x * w as f32 - sx, end_y * h as f32 - sy);
+            let len2 = (dx * dx + dy * dy).max(1e-6);
+            per_pixel(w, h, |x, y| {
+                let t = ((x - sx) * dx + (y - sy) * dy) / len2;
+                1.0 - smoothstep(t)
+            })
+        }
+        MaskShape::Radial { center_x, center_y, radius_x, radius_y, angle, feather } => {
+            let (cx, cy) = (center_x * w as f32, center_y * h as f32);
+            let rx = (radius_x * w as f32).max(1.0);
+            let ry = (radius_y * h as f32).max(1.0);
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let inner = 1.0 - feather.clamp(0.0, 1.0);
+            per_pixel(w, h, |x, y| {
+                let (px, py) = (x - cx, y - cy);
+                let u = (px * cos + py * sin) / rx;
+                let v = (-px * sin + py * cos) / ry;
+                let r = (u * u + v * v).sqrt();
+                if r <= inner {
+                    1.0
+                } else if inner >= 1.0 {
+                    0.0
+                } else {
+                    1.0 - smoothstep((r - inner) / (1.0 - inner))
+                }
+            })
+        }
+        MaskShape::Brush { strokes } => render_brush(strokes, w, h),
+    };
+
+    if mask.invert {
+        alpha.par_iter_mut().for_each(|a| *a = 1.0 - *a);
+    }
+    alpha
+}
+
+fn per_pixel<F>(w: usize, h: usize, f: F) -> Vec<f32>
+where
+    F: Fn(f32, f32) -> f32 + Sync,
+{
+    (0..w * h)
+        .into_par_iter()
+        .map(|idx| f((idx % w) as f32 + 0.5, (idx / w) as f32 + 0.5).clamp(0.0, 1.0))
+        .collect()
+}
+
+/// Rasterizes strokes in order; erase strokes remove coverage painted before.
+fn render_brush(strokes: &[BrushStroke], w: usize, h: usize) -> Vec<f32> {
+    let mut alpha = vec![0.0f32; w * h];
+    let long_edge = w.max(h) as f32;
+
+    for stroke in strokes {
+        let radius = (stroke.size * long_edge).max(0.5);
+        let inner = radius * (1.0 - stroke.feather.clamp(0.0, 1.0));
+        let flow = stroke.flow.clamp(0.0, 1.0);
+        let points: Vec<(f32, f32)> = stroke
+            .points
+            .iter()
+            .map(|p| (p[0] * w as f32, p[1] * h as f32))
+            .collect();
+        if points.is_empty() {
+            continue;
+        }
+
+        let segments: Vec<((f32, f32), (f32, f32))> = if points.len() == 1 {
+            vec![(points[0], points[0])]
+        } else {
+            points.windows(2).map(|s| (s[0], s[1])).collect()
+        };
+
+        for (a, b) in segments {
+            let x0 = (a.0.min(b.0) - radius).floor().max(0.0) as usize;
+            let x1 = ((a.0.max(b.0) + radius).ceil().max(0.0) as usize).min(w);
+            let y0 = (a.1.min(b.1) - radius).floor().max(0.0) as usize;
+            let y1 = ((a.1.max(b.1) + radius).ceil().max(0.0) as usize).min(h);
+            if x0 >= x1 || y0 >= y1 {
+                continue;
+            }
+
+            alpha[y0 * w..y1 * w]
+                .par_chunks_mut(w)
+                .enumerate()
+                .for_each(|(row, line)| {
+                    let y = (y0 + row) as f32 + 0.5;
+                    for (x, value) in line.iter_mut().enumerate().take(x1).skip(x0) {
+                        let d = distance_to_segment((x as f32 + 0.5, y), a, b);
+                        if d >= radius {
+                            continue;
+                        }
+                        let coverage = if d <= inner {
+                            flow
+                        } else {
+                            flow * (1.0 - smoothstep((d - inner) / (radius - inner)))
+                        };
+                        if stroke.erase {
+                            *value = (*value - coverage).max(0.0);
+                        } else {
+                            *value = value.max(coverage);
+                        }
+                    }
+                });
+        }
+    }
+
+    alpha
+}
+
+fn distance_to_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 > 0.0 {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (cx, cy) = (a.0 + t * dx, a.1 + t * dy);
+    ((p.0 - cx).powi(2) + (p.1 - cy).powi(2)).sqrt()
+}
+
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// A local adjustment prepared for per-pixel evaluation on the 0 to 255 scale.
+struct LocalOps {
+    exposure: f32,
+    contrast: Option<f32>,
+    wb_matrix: Option<[[f32; 3]; 3]>,
+    saturation: f32,
+    clarity: f32,
+}
+
+impl LocalOps {
+    fn new(adj: &LocalAdjustment) -> Self {
+        // Map the relative temperature slider to a Kelvin shift in mired so
+        // it feels even across the range; positive warms.
+        let temp = if adj.temperature == 0.0 {
+            white_balance::REFERENCE_TEMP
+        } else {
+            let reference_mired = 1.0e6 / white_balance::REFERENCE_TEMP;
+            1.0e6 / (reference_mired - adj.temperature.clamp(-100.0, 100.0) * 0.8)
+        };
+        let contrast = adj.contrast.clamp(-100.0, 100.0);
+
+        Self {
+            exposure: 2.0_f32.powf(adj.exposure),
+            contrast: (contrast != 0.0)
+                .then(|| (259.0 * (contrast + 255.0)) / (255.0 * (259.0 - contrast))),
+            wb_matrix: white_balance::adaptation_matrix(temp, adj.tint.clamp(-100.0, 100.0)),
+            saturation: 1.0 + adj.saturation.clamp(-100.0, 100.0) / 100.0,
+            clarity: adj.clarity.clamp(-100.0, 100.0) / 100.0,
+        }
+    }
+
+    fn apply(&self, pixel: [f32; 3], detail: f32) -> [f32; 3] {
+        let mut rgb = pixel;
+
+        if let Some(m) = &self.wb_matrix {
+            let lin = rgb.map(|v| white_balance::srgb_to_linear(v / 255.0));
+            for (c, row) in m.iter().enumerate() {
+                let v = row[0] * lin[0] + row[1] * lin[1] + row[2] * lin[2];
+                rgb[c] = white_balance::linear_to_srgb(v.max(0.0)) * 255.0;
+            }
+        }
+
+        for v in rgb.iter_mut() {
+            *v *= self.exposure;
+        }
+
+        if let Some(factor) = self.contrast {
+            for v in rgb.iter_mut() {
+                *v = factor * (*v - 128.0) + 128.0;
+            }
+        }
+
+        if self.clarity != 0.0 {
+            let lum = (0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2]) / 255.0;
+            let midtones = (1.0 - (2.0 * lum - 1.0).powi(2)).max(0.0);
+            let delta = self.clarity * detail * midtones;
+            for v in rgb.iter_mut() {
+                *v += delta;
+            }
+        }
+
+        if self.saturation != 1.0 {
+            let gray = 0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2];
+            for v in rgb.iter_mut() {
+                *v = gray + self.saturation * (*v - gray);
+            }
+        }
+
+        rgb
+    }
+}
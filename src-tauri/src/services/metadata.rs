@@ -0,0 +1,274 @@
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+use tiff::encoder::{DirectoryEncoder, Rational, SRational, TiffKind};
+use tiff::tags::Tag;
+use tiff::TiffResult;
+
+use crate::models::{ExportOptions, MetadataMode};
+use crate::services::exif::{self, ExifData, ExifEntry, Ifd};
+use crate::services::xmp::{self, Description};
+
+/// Embedded XMP packets sit near the start of JPEGs and TIFF-based files.
+const MAX_HEADER_BYTES: u64 = 4 * 1024 * 1024;
+
+/// IFD0 tags that describe the photo rather than how the source file is
+/// laid out. The rest of IFD0 (strips, tiles, previews) does not apply to
+/// the exported pixels.
+const PRIMARY_TAGS: &[u16] = &[
+    exif::TAG_IMAGE_DESCRIPTION,
+    exif::TAG_MAKE,
+    exif::TAG_MODEL,
+    exif::TAG_ORIENTATION,
+    exif::TAG_X_RESOLUTION,
+    exif::TAG_Y_RESOLUTION,
+    exif::TAG_RESOLUTION_UNIT,
+    exif::TAG_DATE_TIME,
+    exif::TAG_ARTIST,
+    exif::TAG_COPYRIGHT,
+];
+
+/// Tags dropped by `MetadataMode::AllExceptGps` besides the GPS IFD.
+const PERSONAL_TAGS: &[u16] = &[exif::TAG_CAMERA_OWNER, exif::TAG_BODY_SERIAL, exif::TAG_LENS_SERIAL];
+
+/// JPEG APP1 segments are limited to 64 KB including this header.
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Metadata to embed in an exported file.
+#[derive(Debug, Default)]
+pub struct ExportMetadata {
+    pub exif: Option<ExifData>,
+    pub xmp: Option<String>,
+}
+
+/// Gathers the metadata to embed for the given options. EXIF comes from the
+/// source file; title and keywords from the sidecar or, failing that, XMP
+/// embedded in the source. Sources are turned upright by their orientation
+/// when decoded, so orientation is reset to normal, and the pixel
+/// dimensions are those of the exported image.
+pub fn collect(
+    image_path: &str,
+    sidecar_path: &str,
+    rating: u8,
+    options: &ExportOptions,
+    width: u32,
+    height: u32,
+) -> ExportMetadata {
+    if options.metadata == MetadataMode::None {
+        return ExportMetadata::default();
+    }
+
+    let mut exif = exif::read_exif(image_path).unwrap_or_default();
+    let mut description = read_description(sidecar_path, image_path);
+    let copyright = options
+        .copyright
+        .clone()
+        .filter(|c| !c.trim().is_empty())
+        .or_else(|| exif.string(Ifd::Primary, exif::TAG_COPYRIGHT))
+        .or(description.rights.take());
+
+    exif.entries.retain(|e| match e.ifd {
+        Ifd::Primary => PRIMARY_TAGS.contains(&e.tag),
+        Ifd::Exif => e.tag != exif::TAG_MAKER_NOTE,
+        Ifd::Gps => true,
+    });
+    match options.metadata {
+        MetadataMode::CopyrightOnly => {
+            exif.entries
+                .retain(|e| e.ifd == Ifd::Primary && matches!(e.tag, exif::TAG_ARTIST | exif::TAG_COPYRIGHT));
+            description = Description::default();
+        }
+        MetadataMode::AllExceptGps => {
+            exif.entries.retain(|e| e.ifd != Ifd::Gps && !PERSONAL_TAGS.contains(&e.tag));
+        }
+        _ => {}
+    }
+    if let Some(copyright) = &copyright {
+        exif.set_string(Ifd::Primary, exif::TAG_COPYRIGHT, copyright);
+    }
+    if options.metadata != MetadataMode::CopyrightOnly {
+        if exif.get(Ifd::Primary, exif::TAG_ORIENTATION).is_some() {
+            exif.set_uint(Ifd::Primary, exif::TAG_ORIENTATION, 1);
+        }
+        if exif.entries.iter().any(|e| e.ifd == Ifd::Exif) {
+            exif.set_uint(Ifd::Exif, exif::TAG_PIXEL_X_DIMENSION, width);
+            exif.set_uint(Ifd::Exif, exif::TAG_PIXEL_Y_DIMENSION, height);
+        }
+    }
+
+    description.rights = copyright;
+    let rating = (options.metadata != MetadataMode::CopyrightOnly && rating > 0).then_some(rating);
+    let has_xmp = rating.is_some()
+        || description.title.is_some()
+        || !description.keywords.is_empty()
+        || description.rights.is_some();
+    let xmp = if has_xmp {
+        xmp::write_export_xmp(rating, &description)
+            .map_err(|e| tracing::warn!("Skipping XMP for {}: {}", image_path, e))
+            .ok()
+    } else {
+        None
+    };
+
+    ExportMetadata {
+        exif: (!exif.entries.is_empty()).then_some(exif),
+        xmp,
+    }
+}
+
+impl ExportMetadata {
+    /// Updates the EXIF pixel dimensions after the image was resized.
+    pub fn set_dimensions(&mut self, width: u32, height: u32) {
+        if let Some(exif) = &mut self.exif {
+            if exif.get(Ifd::Exif, exif::TAG_PIXEL_X_DIMENSION).is_some() {
+                exif.set_uint(Ifd::Exif, exif::TAG_PIXEL_X_DIMENSION, width);
+                exif.set_uint(Ifd::Exif, exif::TAG_PIXEL_Y_DIMENSION, height);
+            }
+        }
+    }
+}
+
+fn read_description(sidecar_path: &str, image_path: &str) -> Description {
+    let from_sidecar = std::fs::read_to_string(sidecar_path)
+        .map(|content| xmp::parse_description(&content))
+        .unwrap_or_default();
+    let from_source = read_embedded_xmp(image_path)
+        .map(|content| xmp::parse_description(&content))
+        .unwrap_or_default();
+    Description {
+        title: from_sidecar.title.or(from_source.title),
+        keywords: if from_sidecar.keywords.is_empty() { from_source.keywords } else { from_sidecar.keywords },
+        rights: from_sidecar.rights.or(from_source.rights),
+    }
+}
+
+/// Finds the XMP packet in a JPEG, TIFF or RAW file by its root element.
+fn read_embedded_xmp(path: &str) -> Option<String> {
+    let mut data = Vec::new();
+    File::open(Path::new(path))
+        .ok()?
+        .take(MAX_HEADER_BYTES)
+        .read_to_end(&mut data)
+        .ok()?;
+    let start = find(&data, b"<x:xmpmeta")?;
+    let end = start + find(&data[start..], b"</x:xmpmeta>")? + b"</x:xmpmeta>".len();
+    String::from_utf8(data[start..end].to_vec()).ok()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Inserts an XMP APP1 segment after the JFIF and EXIF segments.
+pub fn embed_jpeg_xmp(jpeg: &mut Vec<u8>, xmp: &str) -> Result<(), String> {
+    let length = 2 + JPEG_XMP_HEADER.len() + xmp.len();
+    let length = u16::try_from(length).map_err(|_| "XMP too large for JPEG".to_string())?;
+
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF && matches!(jpeg[pos + 1], 0xE0 | 0xE1) {
+        pos += 2 + u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+    }
+
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&length.to_be_bytes());
+    segment.extend_from_slice(JPEG_XMP_HEADER);
+    segment.extend_from_slice(xmp.as_bytes());
+    jpeg.splice(pos..pos, segment);
+    Ok(())
+}
+
+/// Inserts an XMP iTXt chunk before the PNG's IEND chunk.
+pub fn embed_png_xmp(png: &mut Vec<u8>, xmp: &str) -> Result<(), String> {
+    // IEND is always the last 12 bytes
+    let iend = png.len().checked_sub(12).filter(|&p| &png[p + 4..p + 8] == b"IEND").ok_or("Invalid PNG")?;
+
+    // Keyword, then uncompressed, no language tag and no translated keyword
+    let mut body = b"iTXtXML:com.adobe.xmp\0\0\0\0\0".to_vec();
+    body.extend_from_slice(xmp.as_bytes());
+    let length = u32::try_from(body.len() - 4).map_err(|_| "XMP too large for PNG".to_string())?;
+
+    let mut chunk = length.to_be_bytes().to_vec();
+    chunk.extend_from_slice(&body);
+    chunk.extend_from_slice(&crc32(&body).to_be_bytes());
+    png.splice(iend..iend, chunk);
+    Ok(())
+}
+
+/// CRC-32 as used by PNG chunks.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Writes the entries of one IFD as tags of a TIFF directory.
+pub fn write_tiff_tags<W: Write + Seek, K: TiffKind>(
+    directory: &mut DirectoryEncoder<'_, W, K>,
+    exif: &ExifData,
+    ifd: Ifd,
+) -> TiffResult<()> {
+    for entry in exif.entries.iter().filter(|e| e.ifd == ifd) {
+        write_tiff_entry(directory, exif.little_endian, entry)?;
+    }
+    Ok(())
+}
+
+/// Writes one entry with its source byte order converted to the encoder's.
+fn write_tiff_entry<W: Write + Seek, K: TiffKind>(
+    directory: &mut DirectoryEncoder<'_, W, K>,
+    little_endian: bool,
+    entry: &ExifEntry,
+) -> TiffResult<()> {
+    let tag = Tag::from_u16_exhaustive(entry.tag);
+    let data = &entry.data;
+    let u16s = || -> Vec<u16> {
+        data.chunks_exact(2)
+            .map(|b| if little_endian { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) })
+            .collect()
+    };
+    let u32s = || -> Vec<u32> {
+        data.chunks_exact(4)
+            .map(|b| {
+                let b = [b[0], b[1], b[2], b[3]];
+                if little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) }
+            })
+            .collect()
+    };
+
+    match entry.format {
+        1 | 7 => directory.write_tag(tag, data.as_slice()),
+        2 => {
+            // The encoder adds the terminating NUL and only takes ASCII
+            let text = String::from_utf8_lossy(data);
+            let text = text.trim_end_matches('\0');
+            if text.is_ascii() && !text.contains('\0') {
+                directory.write_tag(tag, text)
+            } else {
+                Ok(())
+            }
+        }
+        3 => directory.write_tag(tag, u16s().as_slice()),
+        8 => directory.write_tag(tag, u16s().into_iter().map(|v| v as i16).collect::<Vec<_>>().as_slice()),
+        4 => directory.write_tag(tag, u32s().as_slice()),
+        9 => directory.write_tag(tag, u32s().into_iter().map(|v| v as i32).collect::<Vec<_>>().as_slice()),
+        5 => {
+            let values: Vec<Rational> = u32s().chunks_exact(2).map(|v| Rational { n: v[0], d: v[1] }).collect();
+            directory.write_tag(tag, values.as_slice())
+        }
+        10 => {
+            let values: Vec<SRational> = u32s()
+                .chunks_exact(2)
+                .map(|v| SRational { n: v[0] as i32, d: v[1] as i32 })
+                .collect();
+            directory.write_tag(tag, values.as_slice())
+        }
+        // Signed bytes and floats do not occur in photo metadata
+        _ => Ok(()),
+    }
+}
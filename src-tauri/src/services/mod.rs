@@ -1,7 +1,23 @@
 pub mod ai_processor;
+pub mod color_mixer;
+pub mod contact_sheet;
+pub mod effects;
+pub mod exif;
 pub mod export;
+pub mod export_jobs;
 pub mod filesystem;
+pub mod filters;
+pub mod history;
 pub mod image_processor;
+pub mod lens;
+pub mod lut;
+pub mod masks;
+pub mod metadata;
+pub mod naming;
+pub mod presets;
 pub mod raw_decoder;
 pub mod thumbnail;
+pub mod tone_curve;
+pub mod watermark;
+pub mod white_balance;
 pub mod xmp;
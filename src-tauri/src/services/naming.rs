@@ -0,0 +1,139 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, NaiveDateTime};
+use std::path::{Path, PathBuf};
+
+use crate::models::ImageFile;
+use crate::services::exif;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Values the tokens of a file name template expand to for one file.
+pub struct NameContext<'a> {
+    pub file: &'a ImageFile,
+    /// Position in the export, counted from the chosen start.
+    pub sequence: u32,
+    pub rating: u8,
+    pub custom_text: &'a str,
+    pub recipe: &'a str,
+}
+
+/// A piece of a parsed template.
+enum Part<'a> {
+    Text(&'a str),
+    Token(&'a str, Option<&'a str>),
+}
+
+/// Splits a template into text and `{token}` or `{token:argument}` parts,
+/// checking that every token is known.
+fn parse(template: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            parts.push(Part::Text(&rest[..open]));
+        }
+        let close = rest[open..].find('}').ok_or_else(|| format!("Unclosed token in template: {}", template))?;
+        let token = &rest[open + 1..open + close];
+        let (name, arg) = match token.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (token, None),
+        };
+        match (name, arg) {
+            ("stem" | "folder" | "rating" | "text" | "recipe", None) => {}
+            ("seq", None) => {}
+            ("seq", Some(width)) if width.parse::<usize>().is_ok_and(|w| (1..=10).contains(&w)) => {}
+            ("date", None) => {}
+            ("date", Some(format)) if !StrftimeItems::new(format).any(|i| matches!(i, Item::Error)) => {}
+            _ => return Err(format!("Unknown template token: {{{}}}", token)),
+        }
+        parts.push(Part::Token(name, arg));
+        rest = &rest[open + close + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    Ok(parts)
+}
+
+pub fn validate_template(template: &str) -> Result<(), String> {
+    parse(template).map(|_| ())
+}
+
+/// Expands a template. Token values have path separators replaced, so only
+/// the template's own `/` starts a subfolder.
+pub fn render(template: &str, context: &NameContext) -> Result<String, String> {
+    let parts = parse(template)?;
+    let needs_date = parts.iter().any(|p| matches!(p, Part::Token("date", _)));
+    let date = if needs_date { capture_date(context.file) } else { None };
+
+    let mut rendered = String::new();
+    for part in parts {
+        let value = match part {
+            Part::Text(text) => {
+                rendered.push_str(text);
+                continue;
+            }
+            Part::Token("stem", _) => context.file.export_stem(),
+            Part::Token("folder", _) => Path::new(&context.file.path)
+                .parent()
+                .and_then(|p| p.file_name())
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            Part::Token("rating", _) => context.rating.to_string(),
+            Part::Token("text", _) => context.custom_text.to_string(),
+            Part::Token("recipe", _) => context.recipe.to_string(),
+            Part::Token("seq", width) => {
+                let width = width.and_then(|w| w.parse().ok()).unwrap_or(1);
+                format!("{:0width$}", context.sequence, width = width)
+            }
+            Part::Token(_, format) => date
+                .map(|d| d.format(format.unwrap_or(DEFAULT_DATE_FORMAT)).to_string())
+                .unwrap_or_default(),
+        };
+        rendered.push_str(&value.replace(['/', '\\'], "_"));
+    }
+    Ok(rendered)
+}
+
+/// Capture time from EXIF, or the file's modification time.
+fn capture_date(file: &ImageFile) -> Option<NaiveDateTime> {
+    exif::read_exif(&file.path)
+        .and_then(|e| e.date_taken())
+        .and_then(|d| NaiveDateTime::parse_from_str(&d, "%Y-%m-%dT%H:%M:%S").ok())
+        .or_else(|| DateTime::parse_from_rfc3339(&file.modified_at).ok().map(|d| d.naive_local()))
+}
+
+/// Replaces characters that are unsafe in file names. Leading and trailing
+/// dots and spaces are dropped, as Windows does not keep them.
+pub fn sanitize(component: &str) -> String {
+    component
+        .chars()
+        .map(|c| if "/\\:*?\"<>|".contains(c) || c.is_control() { '_' } else { c })
+        .collect::<String>()
+        .trim_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_string()
+}
+
+/// Path of an export relative to the destination folder: the rendered
+/// subfolder template, then the file name template with the extension.
+pub fn relative_path(
+    filename_template: &str,
+    subfolder_template: &str,
+    extension: &str,
+    context: &NameContext,
+) -> Result<PathBuf, String> {
+    let mut path = PathBuf::new();
+    for component in render(subfolder_template, context)?.split(['/', '\\']) {
+        let component = sanitize(component);
+        if !component.is_empty() {
+            path.push(component);
+        }
+    }
+
+    let name = sanitize(&render(filename_template, context)?);
+    if name.is_empty() {
+        return Err("File name template gives an empty name".to_string());
+    }
+    path.push(format!("{}.{}", name, extension));
+    Ok(path)
+}
@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::models::{EditState, FieldMask, PresetInfo};
+use crate::services::filesystem::get_presets_dir;
+use crate::services::xmp::{self, PresetXmp};
+
+/// Fields a preset can hold, in the order they are listed. Crop is left out
+/// since it depends on the framing of each photo.
+pub const PRESET_FIELDS: &[&str] = &[
+    "rating",
+    "flag",
+    "straightenAngle",
+    "rotation",
+    "exposure",
+    "contrast",
+    "highlights",
+    "shadows",
+    "highlightReconstruction",
+    "whiteBalanceTemp",
+    "whiteBalanceTint",
+    "saturation",
+    "vibrance",
+    "clarity",
+    "texture",
+    "dehaze",
+    "sharpeningAmount",
+    "sharpeningRadius",
+    "sharpeningDetail",
+    "sharpeningMasking",
+    "noiseReduction",
+    "noiseReductionDetail",
+    "colorNoiseReduction",
+    "colorNoiseDetail",
+    "toneCurve",
+    "hsl",
+    "monochrome",
+    "grayMixer",
+    "splitToning",
+    "look",
+    "masks",
+    "lens",
+    "vignette",
+    "grain",
+];
+
+/// Copies the named settings of `source` over `target`. A setting is a
+/// field, replaced whole, or a dotted path within one (e.g. `hsl.hue.2`), so
+/// a Lightroom preset that only sets the orange hue leaves the other bands
+/// alone.
+pub fn merge_fields(target: &EditState, source: &EditState, settings: &[String]) -> Result<EditState, String> {
+    let mut merged = serde_json::to_value(target).map_err(|e| e.to_string())?;
+    let source = serde_json::to_value(source).map_err(|e| e.to_string())?;
+    for setting in settings {
+        let pointer = format!("/{}", setting.replace('.', "/"));
+        let unknown = || format!("Unknown field: {}", setting);
+        let value = source.pointer(&pointer).ok_or_else(unknown)?;
+        *merged.pointer_mut(&pointer).ok_or_else(unknown)? = value.clone();
+    }
+    serde_json::from_value(merged).map_err(|e| e.to_string())
+}
+
+/// The fields a mask selects. Names are checked against `EditState`.
+pub fn resolve_mask(mask: &FieldMask) -> Result<Vec<String>, String> {
+    let all: Vec<String> = match serde_json::to_value(EditState::default()).map_err(|e| e.to_string())? {
+        serde_json::Value::Object(map) => map.keys().cloned().collect(),
+        _ => Vec::new(),
+    };
+    let named = mask.fields.iter().flatten().chain(&mask.exclude);
+    if let Some(unknown) = named.into_iter().find(|f| !all.contains(f)) {
+        return Err(format!("Unknown field: {}", unknown));
+    }
+
+    let fields: Vec<String> = mask
+        .fields
+        .clone()
+        .unwrap_or(all)
+        .into_iter()
+        .filter(|f| !mask.exclude.contains(f))
+        .collect();
+    if fields.is_empty() {
+        return Err("No fields selected".to_string());
+    }
+    Ok(fields)
+}
+
+/// Checks the fields against `PRESET_FIELDS` and puts them in its order.
+fn normalize_fields(fields: &[String]) -> Result<Vec<String>, String> {
+    if let Some(unknown) = fields.iter().find(|f| !PRESET_FIELDS.contains(&f.as_str())) {
+        return Err(format!("Unsupported preset field: {}", unknown));
+    }
+    let normalized: Vec<String> = PRESET_FIELDS
+        .iter()
+        .filter(|f| fields.iter().any(|g| g == *f))
+        .map(|f| f.to_string())
+        .collect();
+    if normalized.is_empty() {
+        return Err("Preset has no settings".to_string());
+    }
+    Ok(normalized)
+}
+
+/// Path of a preset in the presets directory. The file name is the preset
+/// name with characters that are unsafe in file names replaced.
+fn preset_path(name: &str) -> Result<PathBuf, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Preset name is empty".to_string());
+    }
+    let file_name: String = name
+        .chars()
+        .map(|c| if "/\\:*?\"<>|".contains(c) || c.is_control() { '_' } else { c })
+        .collect();
+    Ok(get_presets_dir().join(format!("{}.xmp", file_name)))
+}
+
+fn read_preset(path: &Path) -> Result<PresetXmp, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Read failed: {}", e))?;
+    let mut preset = xmp::parse_preset_xmp(&content)?;
+    preset.fields.retain(|f| PRESET_FIELDS.contains(&f.as_str()));
+    preset.settings.retain(|s| PRESET_FIELDS.contains(&xmp::setting_field(s)));
+    if preset.name.as_deref().is_none_or(|n| n.trim().is_empty()) {
+        preset.name = path.file_stem().map(|s| s.to_string_lossy().to_string());
+    }
+    Ok(preset)
+}
+
+pub fn load_preset(name: &str) -> Result<PresetXmp, String> {
+    let path = preset_path(name)?;
+    if !path.exists() {
+        return Err(format!("Preset not found: {}", name));
+    }
+    read_preset(&path)
+}
+
+/// Presets in the presets directory that parse, sorted by name.
+pub fn list_presets() -> Vec<PresetInfo> {
+    let Ok(entries) = fs::read_dir(get_presets_dir()) else {
+        return Vec::new();
+    };
+    let mut presets: Vec<PresetInfo> = entries
+        .filter_map(|e| e.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case("xmp"))
+        })
+        .filter_map(|path| match read_preset(&path) {
+            Ok(preset) => Some(PresetInfo {
+                name: preset.name.unwrap_or_default(),
+                fields: preset.fields,
+            }),
+            Err(e) => {
+                tracing::warn!("Skipping preset {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+    presets.sort_by_key(|p| p.name.to_lowercase());
+    presets
+}
+
+/// Saves the given fields of `edits` as a preset, replacing one with the
+/// same name.
+pub fn save_preset(name: &str, edits: &EditState, fields: &[String]) -> Result<PresetInfo, String> {
+    let name = name.trim();
+    let fields = normalize_fields(fields)?;
+    write_preset(name, edits, &fields)?;
+    Ok(PresetInfo { name: name.to_string(), fields })
+}
+
+fn write_preset(name: &str, edits: &EditState, settings: &[String]) -> Result<(), String> {
+    let path = preset_path(name)?;
+    let content = xmp::write_preset_xmp(name, edits, settings)?;
+
+    fs::create_dir_all(get_presets_dir()).map_err(|e| format!("Create dir failed: {}", e))?;
+    fs::write(&path, content).map_err(|e| format!("Write failed: {}", e))
+}
+
+/// Imports an XMP preset, e.g. one exported from Lightroom. Settings we do
+/// not support are dropped; the preset is stored in our own format, keeping
+/// only the settings the file had.
+pub fn import_preset(source: &Path) -> Result<PresetInfo, String> {
+    let preset = read_preset(source)?;
+    let name = preset.name.ok_or("Invalid file name")?;
+    if preset.fields.is_empty() {
+        return Err("Preset contains no supported settings".to_string());
+    }
+    let name = name.trim();
+    write_preset(name, &preset.state, &preset.settings)?;
+    Ok(PresetInfo { name: name.to_string(), fields: normalize_fields(&preset.fields)? })
+}
+
+pub fn delete_preset(name: &str) -> Result<(), String> {
+    let path = preset_path(name)?;
+    fs::remove_file(&path).map_err(|e| format!("Delete failed: {}", e))
+}
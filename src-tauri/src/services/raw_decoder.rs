@@ -1,6 +1,11 @@
-use image::{DynamicImage, ImageBuffer, Rgb};
+use image::{DynamicImage, ImageBuffer, Rgb, Rgb32FImage};
+use rayon::prelude::*;
 use std::path::Path;
 
+use crate::models::{Dimensions, HighlightMode};
+use crate::services::filters::gaussian_blur;
+use crate::services::white_balance;
+
 pub struct RawDecoder;
 
 impl RawDecoder {
@@ -70,3 +75,358 @@ impl RawDecoder {
         )
     }
 }
+
+/// Sensor values at or above this fraction of the white level count as clipped.
+const CLIP_THRESHOLD: f32 = 0.98;
+
+/// sRGB (D65) to XYZ.
+const SRGB_TO_XYZ: [[f32; 3]; 3] = [
+    [0.412456, 0.357576, 0.180438],
+    [0.212673, 0.715152, 0.072175],
+    [0.019334, 0.119192, 0.950304],
+];
+
+impl RawDecoder {
+    /// Develops sensor data to sRGB-encoded floats. White balance is the
+    /// camera's as-shot multipliers; values above 1.0 are highlight headroom
+    /// for `apply_edits` to recover. Bayer files are demosaiced at half size
+    /// when that still covers `max_size`. Also returns the full frame size.
+    pub fn develop(
+        path: &Path,
+        mode: HighlightMode,
+        max_size: Option<u32>,
+    ) -> Result<(DynamicImage, Dimensions), String> {
+        let raw = rawloader::decode_file(path).map_err(|e| format!("Failed to decode RAW: {}", e))?;
+
+        let [top, right, bottom, left] = raw.crops;
+        let width = raw.width.saturating_sub(left + right);
+        let height = raw.height.saturating_sub(top + bottom);
+        if width < 2 || height < 2 {
+            return Err("RAW image has no active area".to_string());
+        }
+        let dimensions = Dimensions { width: width as u32, height: height as u32 };
+
+        let sensor = normalize_sensor(&raw, top, left, width, height);
+        let half_size = raw.cpp == 1
+            && raw.cfa.width == 2
+            && raw.cfa.height == 2
+            && max_size.is_some_and(|m| m <= (width.max(height) / 2) as u32);
+
+        let (mut pixels, out_w, out_h) = if raw.cpp >= 3 {
+            let pixels = sensor.chunks(raw.cpp).map(|c| [c[0], c[1], c[2]]).collect();
+            (pixels, width, height)
+        } else if half_size {
+            demosaic_half(&sensor, width, height, |r, c| cfa_color(&raw, top + r, left + c))
+        } else {
+            let pixels = demosaic_bilinear(&sensor, width, height, |r, c| cfa_color(&raw, top + r, left + c));
+            (pixels, width, height)
+        };
+
+        let wb = white_balance_multipliers(&raw);
+        pixels.par_iter_mut().for_each(|p| {
+            for c in 0..3 {
+                p[c] *= wb[c];
+            }
+        });
+        let clip = wb.map(|m| m * CLIP_THRESHOLD);
+
+        match mode {
+            HighlightMode::Clip => clip_highlights(&mut pixels, &clip),
+            HighlightMode::Blend => blend_highlights(&mut pixels, &clip),
+            HighlightMode::Reconstruct => reconstruct_highlights(&mut pixels, out_w, out_h, &clip),
+        }
+
+        let matrix = camera_to_srgb(&raw);
+        let data: Vec<f32> = pixels
+            .par_iter()
+            .flat_map_iter(|p| {
+                (0..3).map(move |c| {
+                    let v = matrix[c][0] * p[0] + matrix[c][1] * p[1] + matrix[c][2] * p[2];
+                    white_balance::linear_to_srgb(v.max(0.0))
+                })
+            })
+            .collect();
+
+        let buffer = Rgb32FImage::from_raw(out_w as u32, out_h as u32, data).ok_or("Failed to create RGB buffer")?;
+        Ok((DynamicImage::ImageRgb32F(buffer), dimensions))
+    }
+}
+
+/// Photosite color of the CFA: 0 red, 1 green, 2 blue. The second green of
+/// four-color patterns counts as green.
+fn cfa_color(raw: &rawloader::RawImage, row: usize, col: usize) -> usize {
+    match raw.cfa.color_at(row, col) {
+        3 => 1,
+        c => c.min(2),
+    }
+}
+
+/// Active area of the sensor scaled so black is 0.0 and white is 1.0.
+fn normalize_sensor(raw: &rawloader::RawImage, top: usize, left: usize, width: usize, height: usize) -> Vec<f32> {
+    let cpp = raw.cpp;
+    let stride = raw.width * cpp;
+    let mut out = vec![0.0f32; width * height * cpp];
+
+    out.par_chunks_mut(width * cpp).enumerate().for_each(|(y, row)| {
+        let src_start = (top + y) * stride + left * cpp;
+        for (i, value) in row.iter_mut().enumerate() {
+            let color = if cpp == 1 { cfa_color(raw, top + y, left + i) } else { i % cpp };
+            let color = color.min(3);
+            *value = match &raw.data {
+                rawloader::RawImageData::Integer(data) => {
+                    let black = raw.blacklevels[color] as f32;
+                    let white = (raw.whitelevels[color] as f32).max(black + 1.0);
+                    (data[src_start + i] as f32 - black) / (white - black)
+                }
+                rawloader::RawImageData::Float(data) => data[src_start + i],
+            };
+        }
+    });
+    out
+}
+
+/// Averages each 2x2 CFA block into one RGB pixel.
+fn demosaic_half<F>(sensor: &[f32], width: usize, height: usize, color_at: F) -> (Vec<[f32; 3]>, usize, usize)
+where
+    F: Fn(usize, usize) -> usize + Sync,
+{
+    let (out_w, out_h) = (width / 2, height / 2);
+    let pixels = (0..out_w * out_h)
+        .into_par_iter()
+        .map(|idx| {
+            let (x, y) = ((idx % out_w) * 2, (idx / out_w) * 2);
+            let mut sum = [0.0f32; 3];
+            let mut count = [0.0f32; 3];
+            for (dy, dx) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                let c = color_at(y + dy, x + dx);
+                sum[c] += sensor[(y + dy) * width + x + dx];
+                count[c] += 1.0;
+            }
+            [0, 1, 2].map(|c| if count[c] > 0.0 { sum[c] / count[c] } else { 0.0 })
+        })
+        .collect();
+    (pixels, out_w, out_h)
+}
+
+/// Bilinear demosaic: each missing color is the mean of that color in the
+/// 3x3 neighborhood. Works for Bayer and X-Trans patterns alike.
+fn demosaic_bilinear<F>(sensor: &[f32], width: usize, height: usize, color_at: F) -> Vec<[f32; 3]>
+where
+    F: Fn(usize, usize) -> usize + Sync,
+{
+    (0..width * height)
+        .into_par_iter()
+        .map(|idx| {
+            let (x, y) = (idx % width, idx / width);
+            let own = color_at(y, x);
+            let mut sum = [0.0f32; 3];
+            let mut count = [0.0f32; 3];
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let c = color_at(ny, nx);
+                    sum[c] += sensor[ny * width + nx];
+                    count[c] += 1.0;
+                }
+            }
+            let mut rgb = [0, 1, 2].map(|c| if count[c] > 0.0 { sum[c] / count[c] } else { 0.0 });
+            rgb[own] = sensor[idx];
+            rgb
+        })
+        .collect()
+}
+
+/// As-shot multipliers normalized to green.
+fn white_balance_multipliers(raw: &rawloader::RawImage) -> [f32; 3] {
+    let wb = raw.wb_coeffs;
+    if !wb[..3].iter().all(|v| v.is_finite() && *v > 0.0) {
+        return [1.0; 3];
+    }
+    [wb[0] / wb[1], 1.0, wb[2] / wb[1]]
+}
+
+/// Camera RGB to linear sRGB, with rows normalized so the white-balanced
+/// camera white maps to sRGB white (as dcraw does).
+fn camera_to_srgb(raw: &rawloader::RawImage) -> [[f32; 3]; 3] {
+    let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    let xyz_to_cam = &raw.xyz_to_cam;
+    if xyz_to_cam[..3].iter().flatten().all(|v| *v == 0.0) {
+        return identity;
+    }
+
+    let mut srgb_to_cam = [[0.0f32; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            srgb_to_cam[i][j] = (0..3).map(|k| xyz_to_cam[i][k] * SRGB_TO_XYZ[k][j]).sum();
+        }
+        let total: f32 = srgb_to_cam[i].iter().sum();
+        if total.abs() > f32::EPSILON {
+            srgb_to_cam[i] = srgb_to_cam[i].map(|v| v / total);
+        }
+    }
+    invert_3x3(&srgb_to_cam).unwrap_or(identity)
+}
+
+fn invert_3x3(m: &[[f32; 3]; 3]) -> Option<[[f32; 3]; 3]> {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    if det.abs() < 1e-9 {
+        return None;
+    }
+    let inv = 1.0 / det;
+    Some([
+        [
+            (m[1][1] * m[2][2] - m[1][2] * m[2][1]) * inv,
+            (m[0][2] * m[2][1] - m[0][1] * m[2][2]) * inv,
+            (m[0][1] * m[1][2] - m[0][2] * m[1][1]) * inv,
+        ],
+        [
+            (m[1][2] * m[2][0] - m[1][0] * m[2][2]) * inv,
+            (m[0][0] * m[2][2] - m[0][2] * m[2][0]) * inv,
+            (m[0][2] * m[1][0] - m[0][0] * m[1][2]) * inv,
+        ],
+        [
+            (m[1][0] * m[2][1] - m[1][1] * m[2][0]) * inv,
+            (m[0][1] * m[2][0] - m[0][0] * m[2][1]) * inv,
+            (m[0][0] * m[1][1] - m[0][1] * m[1][0]) * inv,
+        ],
+    ])
+}
+
+/// Clips every channel at the lowest channel's clip level, so blown areas
+/// turn neutral white and no headroom remains.
+fn clip_highlights(pixels: &mut [[f32; 3]], clip: &[f32; 3]) {
+    let level = clip.iter().copied().fold(f32::MAX, f32::min);
+    pixels.par_iter_mut().for_each(|p| {
+        for v in p.iter_mut() {
+            *v = v.min(level);
+        }
+    });
+}
+
+/// Fades partly clipped pixels toward a neutral at their brightest channel.
+/// Keeps the headroom but gives up the color of blown areas.
+fn blend_highlights(pixels: &mut [[f32; 3]], clip: &[f32; 3]) {
+    let level = clip.iter().copied().fold(f32::MAX, f32::min);
+    pixels.par_iter_mut().for_each(|p| {
+        let max = p[0].max(p[1]).max(p[2]);
+        // Start blending a little below the clip point to avoid a seam
+        let t = ((max - level * 0.9) / (level * 0.1)).clamp(0.0, 1.0);
+        if t > 0.0 {
+            let k = t * t * (3.0 - 2.0 * t);
+            for v in p.iter_mut() {
+                *v += (max - *v) * k;
+            }
+        }
+    });
+}
+
+/// Long edge of the grid highlight color ratios are estimated on. The
+/// smallest blur is 1% of the image's long edge, about five cells here, so
+/// the maps need no finer detail and stay small for any sensor.
+const RATIO_GRID: usize = 512;
+
+/// Rebuilds clipped channels from the unclipped ones, using color ratios of
+/// bright unclipped pixels nearby. Pixels with every channel clipped fall
+/// back to blending.
+fn reconstruct_highlights(pixels: &mut [[f32; 3]], width: usize, height: usize, clip: &[f32; 3]) {
+    let is_clipped = |p: &[f32; 3], c: usize| p[c] >= clip[c];
+    if !pixels.par_iter().any(|p| (0..3).any(|c| is_clipped(p, c))) {
+        return;
+    }
+
+    // Ratio of each channel to the pixel mean, summed per grid cell where
+    // nothing clips but the pixel is bright enough to share the highlight's
+    // color. Each cell holds the weight and the three weighted ratios.
+    let level = clip.iter().copied().fold(f32::MAX, f32::min);
+    let cell = width.max(height).div_ceil(RATIO_GRID).max(1);
+    let (grid_width, grid_height) = (width.div_ceil(cell), height.div_ceil(cell));
+    let mut grid = vec![[0.0f32; 4]; grid_width * grid_height];
+    grid.par_chunks_mut(grid_width).enumerate().for_each(|(gy, row)| {
+        for y in gy * cell..((gy + 1) * cell).min(height) {
+            for (x, p) in pixels[y * width..(y + 1) * width].iter().enumerate() {
+                let mean = (p[0] + p[1] + p[2]) / 3.0;
+                if mean > level * 0.25 && (0..3).all(|c| !is_clipped(p, c)) {
+                    let sums = &mut row[x / cell];
+                    sums[0] += 1.0;
+                    for c in 0..3 {
+                        sums[c + 1] += p[c] / mean;
+                    }
+                }
+            }
+        }
+    });
+    // As a fraction of the cell, so weights read as they would per pixel
+    let area = (cell * cell) as f32;
+    let planes: Vec<Vec<f32>> = (0..4).map(|k| grid.iter().map(|sums| sums[k] / area).collect()).collect();
+    drop(grid);
+
+    // Estimate ratios at growing radii so large blown areas still get a
+    // color from their surroundings, with the frame average as last resort
+    let total_weight: f32 = planes[0].iter().sum();
+    let global = if total_weight > 0.0 {
+        [1, 2, 3].map(|k| planes[k].iter().sum::<f32>() / total_weight)
+    } else {
+        [1.0; 3]
+    };
+    let long_edge = width.max(height) as f32;
+    let scales: Vec<Vec<Vec<f32>>> = [0.01, 0.05, 0.25]
+        .iter()
+        .map(|f| {
+            let sigma = long_edge * f / cell as f32;
+            planes
+                .iter()
+                .map(|plane| gaussian_blur(plane, grid_width, grid_height, sigma))
+                .collect()
+        })
+        .collect();
+    let sample = |plane: &[f32], x: usize, y: usize| {
+        let to_grid = |v: usize| (v as f32 + 0.5) / cell as f32 - 0.5;
+        sample_bilinear(plane, grid_width, grid_height, to_grid(x), to_grid(y))
+    };
+
+    pixels.par_iter_mut().enumerate().for_each(|(i, p)| {
+        let clipped = [0, 1, 2].map(|c| is_clipped(p, c));
+        if !clipped.iter().any(|&c| c) {
+            return;
+        }
+        if clipped.iter().all(|&c| c) {
+            let max = p[0].max(p[1]).max(p[2]);
+            *p = [max; 3];
+            return;
+        }
+
+        let (x, y) = (i % width, i / width);
+        let ratio = scales
+            .iter()
+            .find_map(|planes| {
+                let weight = sample(&planes[0], x, y);
+                (weight > 1e-3).then(|| [1, 2, 3].map(|k| sample(&planes[k], x, y) / weight))
+            })
+            .unwrap_or(global)
+            .map(|r| r.max(1e-3));
+
+        // Pixel mean implied by the unclipped channels and the local ratios
+        let (sum, count) = (0..3)
+            .filter(|&c| !clipped[c])
+            .fold((0.0, 0.0), |(s, n), c| (s + p[c] / ratio[c], n + 1.0));
+        let mean = sum / count;
+        for c in 0..3 {
+            if clipped[c] {
+                p[c] = p[c].max(mean * ratio[c]);
+            }
+        }
+    });
+}
+
+/// Bilinear sample of a plane at fractional coordinates, clamped to its edges.
+fn sample_bilinear(plane: &[f32], width: usize, height: usize, x: f32, y: f32) -> f32 {
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let top = plane[y0 * width + x0] * (1.0 - fx) + plane[y0 * width + x1] * fx;
+    let bottom = plane[y1 * width + x0] * (1.0 - fx) + plane[y1 * width + x1] * fx;
+    top * (1.0 - fy) + bottom * fy
+}
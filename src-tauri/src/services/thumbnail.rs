@@ -1,8 +1,12 @@
-use image::{DynamicImage, Rgb, RgbImage};
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader, Rgb, RgbImage};
 use std::fs;
 use std::path::Path;
 
+use crate::models::{Dimensions, HighlightMode};
+use crate::services::exif::read_exif;
 use crate::services::filesystem::get_thumbnail_dir;
+use crate::services::raw_decoder::RawDecoder;
 
 pub fn generate_thumbnail(image_path: &str, file_id: &str) -> Result<String, String> {
     let thumb_dir = get_thumbnail_dir();
@@ -49,6 +53,7 @@ fn create_placeholder_thumbnail() -> DynamicImage {
     DynamicImage::ImageRgb8(img)
 }
 
+/// Loads an image turned upright by its EXIF orientation.
 pub fn load_image(path: &str) -> Result<DynamicImage, String> {
     let path = Path::new(path);
     let ext = path
@@ -57,12 +62,72 @@ pub fn load_image(path: &str) -> Result<DynamicImage, String> {
         .unwrap_or_default();
 
     if crate::models::ImageFile::is_raw_extension(&ext) {
-        load_raw_image(path)
+        let mut img = load_raw_image(path)?;
+        img.apply_orientation(raw_orientation(path));
+        Ok(img)
     } else {
-        image::open(path).map_err(|e| format!("Failed to open image: {}", e))
+        let open_err = |e: String| format!("Failed to open image: {}", e);
+        let mut decoder = ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|e| open_err(e.to_string()))?
+            .into_decoder()
+            .map_err(|e| open_err(e.to_string()))?;
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+        let mut img = DynamicImage::from_decoder(decoder).map_err(|e| open_err(e.to_string()))?;
+        img.apply_orientation(orientation);
+        Ok(img)
     }
 }
 
+/// Orientation of a RAW file from its EXIF. Sensor data and embedded
+/// previews are stored as the sensor saw them.
+fn raw_orientation(path: &Path) -> Orientation {
+    read_exif(&path.to_string_lossy())
+        .and_then(|exif| exif.orientation())
+        .and_then(|o| u8::try_from(o).ok())
+        .and_then(Orientation::from_exif)
+        .unwrap_or(Orientation::NoTransforms)
+}
+
+/// Loads an image for editing, upright. RAW files are developed from sensor
+/// data so highlight headroom survives; formats the decoder cannot read fall
+/// back to the embedded preview. `max_size` lets the decoder work at reduced
+/// size when the result will be downscaled anyway. Also returns the
+/// full-size dimensions of the upright source.
+pub fn develop_image(
+    path: &str,
+    mode: HighlightMode,
+    max_size: Option<u32>,
+) -> Result<(DynamicImage, Dimensions), String> {
+    let path = Path::new(path);
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if crate::models::ImageFile::is_raw_extension(&ext) {
+        match RawDecoder::develop(path, mode, max_size) {
+            Ok((mut img, dimensions)) => {
+                let orientation = raw_orientation(path);
+                img.apply_orientation(orientation);
+                let dimensions = match orientation {
+                    Orientation::Rotate90
+                    | Orientation::Rotate270
+                    | Orientation::Rotate90FlipH
+                    | Orientation::Rotate270FlipH => Dimensions { width: dimensions.height, height: dimensions.width },
+                    _ => dimensions,
+                };
+                return Ok((img, dimensions));
+            }
+            Err(e) => tracing::debug!("Falling back to embedded preview for {}: {}", path.display(), e),
+        }
+    }
+
+    let img = load_image(&path.to_string_lossy())?;
+    let dimensions = Dimensions { width: img.width(), height: img.height() };
+    Ok((img, dimensions))
+}
+
 fn load_raw_image(path: &Path) -> Result<DynamicImage, String> {
     // Try standard image open first (works for some RAW formats)
     if let Ok(img) = image::open(path) {
@@ -0,0 +1,186 @@
+use crate::models::{CurvePoint, ParametricCurve, ToneCurve};
+
+const LUT_SIZE: usize = 1024;
+
+/// Per-channel lookup tables combining the parametric, master and RGB curves.
+/// Inputs and outputs are on the 0 to 255 scale used by `apply_edits`.
+pub struct ToneCurveLut {
+    channels: [Vec<f32>; 3],
+}
+
+impl ToneCurveLut {
+    /// Builds the tables, or returns `None` when every curve is an identity.
+    pub fn new(curve: &ToneCurve) -> Option<Self> {
+        if *curve == ToneCurve::default() {
+            return None;
+        }
+
+        let parametric = parametric_points(&curve.parametric);
+        let master = Spline::new(&curve.master);
+        let rgb = [
+            Spline::new(&curve.red),
+            Spline::new(&curve.green),
+            Spline::new(&curve.blue),
+        ];
+
+        let channels = rgb.map(|channel| {
+            (0..LUT_SIZE)
+                .map(|i| {
+                    let x = i as f32 * 255.0 / (LUT_SIZE - 1) as f32;
+                    let v = parametric.as_ref().map_or(x, |p| p.eval(x));
+                    let v = master.as_ref().map_or(v, |m| m.eval(v));
+                    channel.as_ref().map_or(v, |c| c.eval(v))
+                })
+                .collect()
+        });
+
+        Some(Self { channels })
+    }
+
+    #[inline]
+    pub fn apply(&self, channel: usize, value: f32) -> f32 {
+        let lut = &self.channels[channel];
+        let pos = value.clamp(0.0, 255.0) * (LUT_SIZE - 1) as f32 / 255.0;
+        let i = (pos as usize).min(LUT_SIZE - 2);
+        let t = pos - i as f32;
+        lut[i] + (lut[i + 1] - lut[i]) * t
+    }
+}
+
+/// The parametric curve as a spline through each region's center, lifted or
+/// lowered by the region's slider. Returns `None` for a flat curve.
+fn parametric_points(p: &ParametricCurve) -> Option<Spline> {
+    let amounts = [p.shadows, p.darks, p.lights, p.highlights];
+    if amounts.iter().all(|&a| a == 0.0) {
+        return None;
+    }
+
+    let s1 = p.shadow_split.clamp(1.0, 97.0);
+    let s2 = p.midtone_split.clamp(s1 + 1.0, 98.0);
+    let s3 = p.highlight_split.clamp(s2 + 1.0, 99.0);
+    let bounds = [0.0, s1, s2, s3, 100.0].map(|b| b * 2.55);
+
+    let mut points = vec![CurvePoint { x: 0.0, y: 0.0 }];
+    for (i, amount) in amounts.iter().enumerate() {
+        let center = (bounds[i] + bounds[i + 1]) / 2.0;
+        let width = bounds[i + 1] - bounds[i];
+        let shift = amount / 100.0 * width * 0.5;
+        points.push(CurvePoint { x: center, y: (center + shift).clamp(0.0, 255.0) });
+    }
+    points.push(CurvePoint { x: 255.0, y: 255.0 });
+
+    for i in 1..points.len() {
+        points[i].y = points[i].y.max(points[i - 1].y);
+    }
+
+    Spline::new(&points)
+}
+
+/// Monotone cubic (Fritsch-Carlson) interpolation through curve points, so
+/// edited curves never overshoot between control points.
+struct Spline {
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    tangents: Vec<f32>,
+}
+
+impl Spline {
+    /// Returns `None` for identity or degenerate curves.
+    fn new(points: &[CurvePoint]) -> Option<Self> {
+        let mut sorted: Vec<CurvePoint> = points
+            .iter()
+            .filter(|p| p.x.is_finite() && p.y.is_finite())
+            .map(|p| CurvePoint { x: p.x.clamp(0.0, 255.0), y: p.y.clamp(0.0, 255.0) })
+            .collect();
+        sorted.sort_by(|a, b| a.x.total_cmp(&b.x));
+        sorted.dedup_by(|a, b| a.x == b.x);
+
+        let spans_range = sorted.first().is_some_and(|p| p.x == 0.0)
+            && sorted.last().is_some_and(|p| p.x == 255.0);
+        if sorted.len() < 2 || (spans_range && sorted.iter().all(|p| p.x == p.y)) {
+            return None;
+        }
+
+        let xs: Vec<f32> = sorted.iter().map(|p| p.x).collect();
+        let ys: Vec<f32> = sorted.iter().map(|p| p.y).collect();
+        let n = xs.len();
+
+        let slopes: Vec<f32> = (0..n - 1)
+            .map(|i| (ys[i + 1] - ys[i]) / (xs[i + 1] - xs[i]))
+            .collect();
+
+        let mut tangents = vec![0.0; n];
+        tangents[0] = slopes[0];
+        tangents[n - 1] = slopes[n - 2];
+        for i in 1..n - 1 {
+            tangents[i] = if slopes[i - 1] * slopes[i] <= 0.0 {
+                0.0
+            } else {
+                (slopes[i - 1] + slopes[i]) / 2.0
+            };
+        }
+
+        for i in 0..n - 1 {
+            if slopes[i] == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+                continue;
+            }
+            let a = tangents[i] / slopes[i];
+            let b = tangents[i + 1] / slopes[i];
+            let h = a * a + b * b;
+            if h > 9.0 {
+                let t = 3.0 / h.sqrt();
+                tangents[i] = t * a * slopes[i];
+                tangents[i + 1] = t * b * slopes[i];
+            }
+        }
+
+        Some(Self { xs, ys, tangents })
+    }
+
+    fn eval(&self, x: f32) -> f32 {
+        let n = self.xs.len();
+        if x <= self.xs[0] {
+            return self.ys[0];
+        }
+        if x >= self.xs[n - 1] {
+            return self.ys[n - 1];
+        }
+
+        let i = self.xs.partition_point(|&px| px <= x) - 1;
+        let h = self.xs[i + 1] - self.xs[i];
+        let t = (x - self.xs[i]) / h;
+        let t2 = t * t;
+        let t3 = t2 * t;
+
+        let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+        let h10 = t3 - 2.0 * t2 + t;
+        let h01 = -2.0 * t3 + 3.0 * t2;
+        let h11 = t3 - t2;
+
+        (h00 * self.ys[i] + h10 * h * self.tangents[i] + h01 * self.ys[i + 1] + h11 * h * self.tangents[i + 1])
+            .clamp(0.0, 255.0)
+    }
+}
+
+/// Parses Lightroom's `"x, y"` curve point strings.
+pub fn parse_curve_points(items: &[String]) -> Vec<CurvePoint> {
+    items
+        .iter()
+        .filter_map(|item| {
+            let (x, y) = item.split_once(',')?;
+            Some(CurvePoint {
+                x: x.trim().parse().ok()?,
+                y: y.trim().parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+pub fn format_curve_points(points: &[CurvePoint]) -> Vec<String> {
+    points
+        .iter()
+        .map(|p| format!("{}, {}", p.x.round(), p.y.round()))
+        .collect()
+}
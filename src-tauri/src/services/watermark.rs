@@ -0,0 +1,254 @@
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, ImageFormat, Luma, Rgba, RgbaImage};
+use imageproc::drawing::{draw_text_mut, text_size};
+use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::models::{Watermark, WatermarkAnchor, WatermarkContent};
+use crate::services::filesystem::get_watermarks_dir;
+use crate::services::image_processor::is_high_depth;
+
+/// Fonts tried, in order, for text without a font of its own.
+const SYSTEM_FONTS: &[&str] = &[
+    "/System/Library/Fonts/Helvetica.ttc",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+    "C:\\Windows\\Fonts\\segoeui.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+];
+
+/// Text is laid out at this size, then scaled to the watermark width.
+const LAYOUT_PX: f32 = 100.0;
+
+/// File of a preset, with characters unsafe in file names replaced.
+fn preset_path(name: &str, extension: &str) -> Result<PathBuf, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Watermark name is empty".to_string());
+    }
+    let file_name: String = name
+        .chars()
+        .map(|c| if "/\\:*?\"<>|".contains(c) || c.is_control() { '_' } else { c })
+        .collect();
+    Ok(get_watermarks_dir().join(format!("{}.{}", file_name, extension)))
+}
+
+pub fn load_watermark(name: &str) -> Result<Watermark, String> {
+    let path = preset_path(name, "json")?;
+    if !path.exists() {
+        return Err(format!("Watermark not found: {}", name));
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("Read failed: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid watermark {}: {}", name, e))
+}
+
+/// Watermark presets that parse, sorted by name.
+pub fn list_watermarks() -> Vec<Watermark> {
+    let Ok(entries) = fs::read_dir(get_watermarks_dir()) else {
+        return Vec::new();
+    };
+    let mut watermarks: Vec<Watermark> = entries
+        .filter_map(|e| e.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .filter_map(|path| {
+            let parsed = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| serde_json::from_str::<Watermark>(&content).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(watermark) => Some(watermark),
+                Err(e) => {
+                    tracing::warn!("Skipping watermark {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect();
+    watermarks.sort_by_key(|w| w.name.to_lowercase());
+    watermarks
+}
+
+/// Saves a preset, replacing one with the same name. An image watermark is
+/// stored as a PNG in the watermarks directory so the preset keeps working if
+/// the original file moves.
+pub fn save_watermark(watermark: &Watermark) -> Result<Watermark, String> {
+    let mut watermark = watermark.clone();
+    watermark.name = watermark.name.trim().to_string();
+    validate(&watermark)?;
+    let path = preset_path(&watermark.name, "json")?;
+    fs::create_dir_all(get_watermarks_dir()).map_err(|e| format!("Create dir failed: {}", e))?;
+
+    if let WatermarkContent::Image { path: source } = &mut watermark.content {
+        let stored = preset_path(&watermark.name, "png")?;
+        if Path::new(source.as_str()) != stored {
+            // Re-encoded rather than copied, so a JPEG or WebP logo is not
+            // left behind a .png name
+            let img = image::open(source.as_str()).map_err(|e| format!("Failed to open watermark image: {}", e))?;
+            img.to_rgba8()
+                .save_with_format(&stored, ImageFormat::Png)
+                .map_err(|e| format!("Write failed: {}", e))?;
+            *source = stored.to_string_lossy().to_string();
+        }
+    }
+
+    let content = serde_json::to_string_pretty(&watermark).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| format!("Write failed: {}", e))?;
+    Ok(watermark)
+}
+
+pub fn delete_watermark(name: &str) -> Result<(), String> {
+    let path = preset_path(name, "json")?;
+    fs::remove_file(&path).map_err(|e| format!("Delete failed: {}", e))?;
+    let _ = fs::remove_file(preset_path(name, "png")?);
+    Ok(())
+}
+
+fn validate(watermark: &Watermark) -> Result<(), String> {
+    if !(watermark.scale > 0.0 && watermark.scale <= 1.0) {
+        return Err("Watermark scale must be above 0 and at most 1".to_string());
+    }
+    if !(0.0..=1.0).contains(&watermark.opacity) {
+        return Err("Watermark opacity must be 0 to 1".to_string());
+    }
+    if !(0.0..0.5).contains(&watermark.margin) {
+        return Err("Watermark margin must be 0 to 0.5".to_string());
+    }
+    if !watermark.rotation.is_finite() {
+        return Err("Invalid watermark rotation".to_string());
+    }
+    // Loads the font or image, so a broken preset fails here rather than
+    // on export
+    render(watermark, 64).map(|_| ())
+}
+
+/// Draws the watermark on an image, keeping 16 bits per channel when the
+/// image has them.
+pub fn apply_watermark(img: DynamicImage, watermark: &Watermark) -> Result<DynamicImage, String> {
+    let (width, height) = (img.width(), img.height());
+    let target_width = ((width as f32 * watermark.scale).round() as u32).max(1);
+    let mut mark = render(watermark, target_width)?;
+
+    if watermark.rotation.rem_euclid(360.0) != 0.0 {
+        // Transparent pixels take the text color so rotated edges do not
+        // darken
+        let clear = match &watermark.content {
+            WatermarkContent::Text { color: [r, g, b], .. } => Rgba([*r, *g, *b, 0]),
+            WatermarkContent::Image { .. } => Rgba([0, 0, 0, 0]),
+        };
+        mark = rotate(&mark, watermark.rotation, clear);
+    }
+
+    let margin = (width.min(height) as f32 * watermark.margin).round() as i64;
+    let (mark_w, mark_h) = (mark.width() as i64, mark.height() as i64);
+    let (w, h) = (width as i64, height as i64);
+    let x = match watermark.anchor {
+        WatermarkAnchor::TopLeft | WatermarkAnchor::Left | WatermarkAnchor::BottomLeft => margin,
+        WatermarkAnchor::Top | WatermarkAnchor::Center | WatermarkAnchor::Bottom => (w - mark_w) / 2,
+        _ => w - mark_w - margin,
+    };
+    let y = match watermark.anchor {
+        WatermarkAnchor::TopLeft | WatermarkAnchor::Top | WatermarkAnchor::TopRight => margin,
+        WatermarkAnchor::Left | WatermarkAnchor::Center | WatermarkAnchor::Right => (h - mark_h) / 2,
+        _ => h - mark_h - margin,
+    };
+
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+    let covered = mark.enumerate_pixels().filter_map(|(mx, my, pixel)| {
+        let (px, py) = (x + mx as i64, y + my as i64);
+        if px < 0 || py < 0 || px >= w || py >= h {
+            return None;
+        }
+        let alpha = pixel[3] as f32 / 255.0 * opacity;
+        (alpha > 0.0).then_some((px as u32, py as u32, pixel, alpha))
+    });
+
+    if is_high_depth(&img) {
+        let mut base = img.into_rgb16();
+        for (px, py, pixel, alpha) in covered {
+            let target = base.get_pixel_mut(px, py);
+            for c in 0..3 {
+                let mark = pixel[c] as f32 * 257.0;
+                target[c] = (target[c] as f32 * (1.0 - alpha) + mark * alpha).round() as u16;
+            }
+        }
+        Ok(DynamicImage::ImageRgb16(base))
+    } else {
+        let mut base = img.into_rgb8();
+        for (px, py, pixel, alpha) in covered {
+            let target = base.get_pixel_mut(px, py);
+            for c in 0..3 {
+                target[c] = (target[c] as f32 * (1.0 - alpha) + pixel[c] as f32 * alpha).round() as u8;
+            }
+        }
+        Ok(DynamicImage::ImageRgb8(base))
+    }
+}
+
+/// The watermark at the given width, before rotation.
+fn render(watermark: &Watermark, width: u32) -> Result<RgbaImage, String> {
+    match &watermark.content {
+        WatermarkContent::Text { text, font_path, color } => {
+            let text = text.trim();
+            if text.is_empty() {
+                return Err("Watermark text is empty".to_string());
+            }
+            let font = load_font(font_path.as_deref())?;
+            let (layout_width, _) = text_size(PxScale::from(LAYOUT_PX), &font, text);
+            if layout_width == 0 {
+                return Err("Watermark text has no visible characters".to_string());
+            }
+            let scale = PxScale::from(LAYOUT_PX * width as f32 / layout_width as f32);
+            let scaled = font.as_scaled(scale);
+            let (text_width, _) = text_size(scale, &font, text);
+            let line_height = (scaled.ascent() - scaled.descent()).ceil().max(1.0) as u32;
+
+            // Coverage first, so edges blend with the photo rather than
+            // with a transparent black background
+            let mut coverage = GrayImage::new(text_width.max(1) + 2, line_height);
+            draw_text_mut(&mut coverage, Luma([255]), 1, 0, scale, &font, text);
+            let [r, g, b] = *color;
+            Ok(RgbaImage::from_fn(coverage.width(), coverage.height(), |x, y| {
+                Rgba([r, g, b, coverage.get_pixel(x, y)[0]])
+            }))
+        }
+        WatermarkContent::Image { path } => {
+            let img = image::open(path).map_err(|e| format!("Failed to open watermark image: {}", e))?;
+            let height = ((img.height() as f64 * width as f64 / img.width() as f64).round() as u32).max(1);
+            Ok(img.resize_exact(width, height, FilterType::Lanczos3).to_rgba8())
+        }
+    }
+}
+
+pub fn load_font(font_path: Option<&str>) -> Result<FontVec, String> {
+    let path = match font_path {
+        Some(path) => path,
+        None => SYSTEM_FONTS
+            .iter()
+            .copied()
+            .find(|p| Path::new(p).exists())
+            .ok_or("No system font found; set fontPath")?,
+    };
+    let data = fs::read(path).map_err(|e| format!("Failed to read font: {}", e))?;
+    // Index 0 also picks the first face of a font collection (.ttc)
+    FontVec::try_from_vec_and_index(data, 0).map_err(|_| format!("Invalid font: {}", path))
+}
+
+/// Rotates on a canvas large enough to keep the corners.
+fn rotate(mark: &RgbaImage, degrees: f32, clear: Rgba<u8>) -> RgbaImage {
+    let theta = -degrees.to_radians();
+    let (w, h) = (mark.width() as f32, mark.height() as f32);
+    let (sin, cos) = (theta.sin().abs(), theta.cos().abs());
+    let side_w = (w * cos + h * sin).ceil() as u32;
+    let side_h = (w * sin + h * cos).ceil() as u32;
+
+    let mut canvas = RgbaImage::from_pixel(side_w.max(mark.width()), side_h.max(mark.height()), clear);
+    let offset_x = (canvas.width() - mark.width()) / 2;
+    let offset_y = (canvas.height() - mark.height()) / 2;
+    image::imageops::replace(&mut canvas, mark, offset_x as i64, offset_y as i64);
+    rotate_about_center(&canvas, theta, Interpolation::Bilinear, clear)
+}
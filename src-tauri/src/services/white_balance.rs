@@ -0,0 +1,249 @@
+//! Temperature/tint white balance.
+//!
+//! Temperature and tint describe the illuminant the photo was lit by. They are
+//! converted to a white point (Planckian locus plus an offset along the
+//! isotherm, as in the DNG SDK) and the image is adapted from that white to the
+//! reference white with a Bradford transform in linear sRGB.
+
+pub const REFERENCE_TEMP: f32 = 5500.0;
+pub const REFERENCE_TINT: f32 = 0.0;
+
+pub const MIN_TEMP: f32 = 2000.0;
+pub const MAX_TEMP: f32 = 50000.0;
+pub const MAX_TINT: f32 = 150.0;
+
+type Mat3 = [[f64; 3]; 3];
+
+const TINT_SCALE: f64 = -3000.0;
+
+/// Robertson isotemperature lines: (mired, u, v, slope).
+const TEMP_TABLE: [(f64, f64, f64, f64); 31] = [
+    (0.0, 0.18006, 0.26352, -0.24341),
+    (10.0, 0.18066, 0.26589, -0.25479),
+    (20.0, 0.18133, 0.26846, -0.26876),
+    (30.0, 0.18208, 0.27119, -0.28539),
+    (40.0, 0.18293, 0.27407, -0.30470),
+    (50.0, 0.18388, 0.27709, -0.32675),
+    (60.0, 0.18494, 0.28021, -0.35156),
+    (70.0, 0.18611, 0.28342, -0.37915),
+    (80.0, 0.18740, 0.28668, -0.40955),
+    (90.0, 0.18880, 0.28997, -0.44278),
+    (100.0, 0.19032, 0.29326, -0.47888),
+    (125.0, 0.19462, 0.30141, -0.58204),
+    (150.0, 0.19962, 0.30921, -0.70471),
+    (175.0, 0.20525, 0.31647, -0.84901),
+    (200.0, 0.21142, 0.32312, -1.0182),
+    (225.0, 0.21807, 0.32909, -1.2168),
+    (250.0, 0.22511, 0.33439, -1.4512),
+    (275.0, 0.23247, 0.33904, -1.7298),
+    (300.0, 0.24010, 0.34308, -2.0637),
+    (325.0, 0.24702, 0.34655, -2.4681),
+    (350.0, 0.25591, 0.34951, -2.9641),
+    (375.0, 0.26400, 0.35200, -3.5814),
+    (400.0, 0.27218, 0.35407, -4.3633),
+    (425.0, 0.28039, 0.35577, -5.3762),
+    (450.0, 0.28863, 0.35714, -6.7262),
+    (475.0, 0.29685, 0.35823, -8.5955),
+    (500.0, 0.30505, 0.35907, -11.324),
+    (525.0, 0.31320, 0.35968, -15.628),
+    (550.0, 0.32129, 0.36011, -23.325),
+    (575.0, 0.32931, 0.36038, -40.770),
+    (600.0, 0.33724, 0.36051, -116.45),
+];
+
+const BRADFORD: Mat3 = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+const BRADFORD_INV: Mat3 = [
+    [0.9869929, -0.1470543, 0.1599627],
+    [0.4323053, 0.5183603, 0.0492912],
+    [-0.0085287, 0.0400428, 0.9684867],
+];
+
+const SRGB_TO_XYZ: Mat3 = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+];
+
+const XYZ_TO_SRGB: Mat3 = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+const D65_XYZ: [f64; 3] = [0.95047, 1.0, 1.08883];
+
+/// Chromaticity of the white point for a temperature (Kelvin) and tint.
+pub fn temp_tint_to_xy(temp: f32, tint: f32) -> (f64, f64) {
+    let r = 1.0e6 / (temp.clamp(MIN_TEMP, MAX_TEMP) as f64);
+    let offset = tint as f64 / TINT_SCALE;
+
+    let mut index = 0;
+    while index < 29 && r >= TEMP_TABLE[index + 1].0 {
+        index += 1;
+    }
+    let (r0, u0, v0, t0) = TEMP_TABLE[index];
+    let (r1, u1, v1, t1) = TEMP_TABLE[index + 1];
+
+    let f = (r1 - r) / (r1 - r0);
+    let mut u = u0 * f + u1 * (1.0 - f);
+    let mut v = v0 * f + v1 * (1.0 - f);
+
+    let len0 = (1.0 + t0 * t0).sqrt();
+    let len1 = (1.0 + t1 * t1).sqrt();
+    let mut du = f / len0 + (1.0 - f) / len1;
+    let mut dv = f * t0 / len0 + (1.0 - f) * t1 / len1;
+    let len = (du * du + dv * dv).sqrt();
+    du /= len;
+    dv /= len;
+
+    u += du * offset;
+    v += dv * offset;
+
+    let denom = u - 4.0 * v + 2.0;
+    (1.5 * u / denom, v / denom)
+}
+
+/// Temperature (Kelvin) and tint whose white point has chromaticity `(x, y)`.
+pub fn xy_to_temp_tint(x: f64, y: f64) -> (f32, f32) {
+    let denom = 1.5 - x + 6.0 * y;
+    let u = 2.0 * x / denom;
+    let v = 3.0 * y / denom;
+
+    let mut last_dt = 0.0;
+    let mut last_du = 0.0;
+    let mut last_dv = 0.0;
+
+    for index in 1..TEMP_TABLE.len() {
+        let (r1, u1, v1, t1) = TEMP_TABLE[index];
+        let len = (1.0 + t1 * t1).sqrt();
+        let mut du = 1.0 / len;
+        let mut dv = t1 / len;
+
+        let mut dt = -(u - u1) * dv + (v - v1) * du;
+
+        if dt <= 0.0 || index == TEMP_TABLE.len() - 1 {
+            dt = -dt.min(0.0);
+            let f = if index == 1 { 0.0 } else { dt / (last_dt + dt) };
+
+            let (r0, u0, v0, _) = TEMP_TABLE[index - 1];
+            let mired = r0 * f + r1 * (1.0 - f);
+            let temp = if mired > 0.0 { 1.0e6 / mired } else { MAX_TEMP as f64 };
+
+            let uu = u - (u0 * f + u1 * (1.0 - f));
+            let vv = v - (v0 * f + v1 * (1.0 - f));
+
+            du = du * (1.0 - f) + last_du * f;
+            dv = dv * (1.0 - f) + last_dv * f;
+            let len = (du * du + dv * dv).sqrt();
+            du /= len;
+            dv /= len;
+
+            let tint = (uu * du + vv * dv) * TINT_SCALE;
+            return (
+                (temp as f32).clamp(MIN_TEMP, MAX_TEMP),
+                (tint as f32).clamp(-MAX_TINT, MAX_TINT),
+            );
+        }
+
+        last_dt = dt;
+        last_du = du;
+        last_dv = dv;
+    }
+
+    (REFERENCE_TEMP, REFERENCE_TINT)
+}
+
+/// Linear sRGB matrix adapting an image lit by `temp`/`tint` to the reference
+/// white. Returns `None` when the settings are the reference (a no-op).
+pub fn adaptation_matrix(temp: f32, tint: f32) -> Option<[[f32; 3]; 3]> {
+    if temp == REFERENCE_TEMP && tint == REFERENCE_TINT {
+        return None;
+    }
+
+    let source = xy_to_xyz(temp_tint_to_xy(temp, tint));
+    let target = xy_to_xyz(temp_tint_to_xy(REFERENCE_TEMP, REFERENCE_TINT));
+
+    let source_cone = mat_vec(&BRADFORD, source);
+    let target_cone = mat_vec(&BRADFORD, target);
+    let mut scale = [[0.0; 3]; 3];
+    for i in 0..3 {
+        scale[i][i] = target_cone[i] / source_cone[i];
+    }
+
+    let xyz = mat_mul(&BRADFORD_INV, &mat_mul(&scale, &mat_mul(&BRADFORD, &SRGB_TO_XYZ)));
+    let m = mat_mul(&XYZ_TO_SRGB, &xyz);
+
+    let mut out = [[0.0f32; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            out[i][j] = m[i][j] as f32;
+        }
+    }
+    Some(out)
+}
+
+/// Temperature and tint that render a patch with the given linear sRGB
+/// average as neutral gray.
+pub fn temp_tint_for_neutral(rgb: [f32; 3]) -> (f32, f32) {
+    let sample = mat_vec(&SRGB_TO_XYZ, [rgb[0] as f64, rgb[1] as f64, rgb[2] as f64]);
+    let reference = xy_to_xyz(temp_tint_to_xy(REFERENCE_TEMP, REFERENCE_TINT));
+
+    let sample_cone = mat_vec(&BRADFORD, sample);
+    let reference_cone = mat_vec(&BRADFORD, reference);
+    let d65_cone = mat_vec(&BRADFORD, D65_XYZ);
+
+    let mut source_cone = [0.0; 3];
+    for i in 0..3 {
+        source_cone[i] = sample_cone[i] * reference_cone[i] / d65_cone[i];
+    }
+
+    let source = mat_vec(&BRADFORD_INV, source_cone);
+    let sum = source[0] + source[1] + source[2];
+    if sum <= 0.0 {
+        return (REFERENCE_TEMP, REFERENCE_TINT);
+    }
+    xy_to_temp_tint(source[0] / sum, source[1] / sum)
+}
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn xy_to_xyz((x, y): (f64, f64)) -> [f64; 3] {
+    [x / y, 1.0, (1.0 - x - y) / y]
+}
+
+fn mat_vec(m: &Mat3, v: [f64; 3]) -> [f64; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+fn mat_mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut out = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            out[i][j] = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
@@ -1,75 +1,123 @@
-use quick_xml::events::{BytesEnd, BytesStart, Event};
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::{Reader, Writer};
 use std::io::Cursor;
 
-use crate::models::{EditState, Flag};
+use crate::models::{EditState, Flag, HighlightMode, LutInterpolation};
+use crate::services::color_mixer::HSL_BANDS;
+use crate::services::tone_curve::{format_curve_points, parse_curve_points};
+
+/// Settings read from an XMP file along with the `EditState` fields it sets.
+/// `settings` are the values within those fields that are set, which is what
+/// a preset applies (see `setting_for_key`).
+pub struct PresetXmp {
+    pub name: Option<String>,
+    pub state: EditState,
+    pub fields: Vec<String>,
+    pub settings: Vec<String>,
+}
 
 pub fn parse_xmp(content: &str) -> Result<EditState, String> {
+    parse_preset_xmp(content).map(|preset| preset.state)
+}
+
+/// Parses a preset or sidecar. Presets written by us list their settings in
+/// `photocull:PresetFields`; for others (e.g. Lightroom presets) the settings
+/// are those present.
+pub fn parse_preset_xmp(content: &str) -> Result<PresetXmp, String> {
     if content.len() > 1024 * 1024 {
         return Err("XMP too large".to_string());
     }
 
     let mut state = EditState::default();
+    let mut title = None;
+    let mut settings: Vec<String> = Vec::new();
+    let mut listed_settings: Option<Vec<String>> = None;
+    let mut note_setting = |key: &str| {
+        if let Some(setting) = setting_for_key(key) {
+            if !settings.contains(&setting) {
+                settings.push(setting);
+            }
+        }
+    };
     let mut reader = Reader::from_str(content);
     reader.trim_text(true);
 
     let mut buf = Vec::new();
+    // Property written as a child element of rdf:Description, with either a
+    // text value or an rdf:Seq of rdf:li items.
+    let mut property: Option<String> = None;
+    let mut items: Vec<String> = Vec::new();
+    let mut in_item = false;
+    let mut nested = 0usize;
 
     loop {
         match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"rdf:Description" && (nested > 0 || property.is_some()) => {
+                // A description inside a property, e.g. the parameters of a
+                // Lightroom profile: none of it is ours.
+                nested += 1;
+            }
+            Ok(Event::Empty(e)) if e.name().as_ref() == b"rdf:Description" && (nested > 0 || property.is_some()) => {
+                // The same, written as a self-closing element
+            }
+            Ok(Event::End(e)) if e.name().as_ref() == b"rdf:Description" && nested > 0 => nested -= 1,
+            Ok(event) if nested > 0 && !matches!(event, Event::Eof) => {}
             Ok(Event::Empty(e)) | Ok(Event::Start(e)) => {
-                if e.name().as_ref() == b"rdf:Description" {
-                    for attr in e.attributes().filter_map(|a| a.ok()) {
-                        let key = String::from_utf8_lossy(attr.key.as_ref()).to_string();
-                        let value = String::from_utf8_lossy(&attr.value).to_string();
-
-                        match key.as_str() {
-                            "xmp:Rating" => {
-                                state.rating = value.parse().unwrap_or(0);
-                            }
-                            "crs:Exposure2012" | "crs:Exposure" => {
-                                state.exposure = value.parse().unwrap_or(0.0);
-                            }
-                            "crs:Contrast2012" | "crs:Contrast" => {
-                                state.contrast = value.parse().unwrap_or(0.0);
-                            }
-                            "crs:Temperature" => {
-                                state.white_balance_temp = value.parse().unwrap_or(5500.0);
-                            }
-                            "crs:Tint" => {
-                                state.white_balance_tint = value.parse().unwrap_or(0.0);
-                            }
-                            "crs:Saturation" => {
-                                state.saturation = value.parse().unwrap_or(0.0);
+                let name = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                match name.as_str() {
+                    "rdf:Description" => {
+                        for attr in e.attributes().filter_map(|a| a.ok()) {
+                            let key = String::from_utf8_lossy(attr.key.as_ref()).to_string();
+                            let value = attr
+                                .unescape_value()
+                                .map(|v| v.to_string())
+                                .unwrap_or_else(|_| String::from_utf8_lossy(&attr.value).to_string());
+                            match key.as_str() {
+                                "crs:Name" => title = Some(value),
+                                "photocull:PresetFields" => listed_settings = Some(split_fields(&value)),
+                                _ => {
+                                    note_setting(&key);
+                                    apply_attribute(&mut state, &key, &value);
+                                }
                             }
-                            "crs:Vibrance" => {
-                                state.vibrance = value.parse().unwrap_or(0.0);
-                            }
-                            "crs:Sharpness" => {
-                                state.sharpening_amount = value.parse().unwrap_or(0.0);
-                            }
-                            "crs:SharpenRadius" => {
-                                state.sharpening_radius = value.parse().unwrap_or(1.0);
-                            }
-                            "crs:CropAngle" => {
-                                state.straighten_angle = value.parse().unwrap_or(0.0);
-                            }
-                            "crs:Orientation" => {
-                                state.rotation = match value.as_str() {
-                                    "6" => 90,
-                                    "3" => 180,
-                                    "8" => 270,
-                                    _ => 0,
-                                };
-                            }
-                            "photocull:Flag" => {
-                                state.flag = match value.as_str() {
-                                    "pick" => Flag::Pick,
-                                    "reject" => Flag::Reject,
-                                    _ => Flag::None,
-                                };
+                        }
+                    }
+                    "rdf:li" => in_item = true,
+                    "rdf:Seq" | "rdf:Bag" | "rdf:Alt" => {}
+                    _ if is_property(&name) => {
+                        property = Some(name);
+                        items.clear();
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Text(t)) => {
+                if let Some(key) = &property {
+                    let text = t.unescape().map_err(|e| format!("XML parse error: {}", e))?;
+                    if in_item {
+                        items.push(text.to_string());
+                    } else if key == "crs:Name" {
+                        title = Some(text.to_string());
+                    } else {
+                        note_setting(key);
+                        apply_attribute(&mut state, key, &text);
+                    }
+                }
+            }
+            Ok(Event::End(e)) => {
+                let name = e.name();
+                if name.as_ref() == b"rdf:li" {
+                    in_item = false;
+                } else if property.as_deref().map(str::as_bytes) == Some(name.as_ref()) {
+                    if let Some(key) = property.take() {
+                        if key == "crs:Name" {
+                            // Localized title in an rdf:Alt; the first entry is x-default
+                            title = items.first().cloned().or(title);
+                        } else {
+                            if !items.is_empty() {
+                                note_setting(&key);
                             }
-                            _ => {}
+                            apply_sequence(&mut state, &key, &items);
                         }
                     }
                 }
@@ -81,10 +129,408 @@ pub fn parse_xmp(content: &str) -> Result<EditState, String> {
         buf.clear();
     }
 
-    Ok(state)
+    let settings = listed_settings.unwrap_or(settings);
+    let mut fields: Vec<String> = Vec::new();
+    for field in settings.iter().map(|s| setting_field(s)) {
+        if !fields.iter().any(|f| f == field) {
+            fields.push(field.to_string());
+        }
+    }
+    Ok(PresetXmp { name: title, state, fields, settings })
+}
+
+/// The `EditState` field a setting belongs to.
+pub fn setting_field(setting: &str) -> &str {
+    setting.split('.').next().unwrap_or(setting)
+}
+
+fn split_fields(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn is_property(name: &str) -> bool {
+    name.starts_with("crs:") || name.starts_with("xmp:") || name.starts_with("photocull:")
+}
+
+/// The value an XMP key sets, as a dotted path of JSON names into
+/// `EditState` (e.g. `hsl.hue.2` for `crs:HueAdjustmentOrange`). Keys that
+/// hold a whole field give the field name; keys of a grouped field that we
+/// do not read give `None`.
+pub fn setting_for_key(key: &str) -> Option<String> {
+    let field = field_for_key(key)?;
+    let setting = match key {
+        "crs:ToneCurvePV2012" => "toneCurve.master",
+        "crs:ToneCurvePV2012Red" => "toneCurve.red",
+        "crs:ToneCurvePV2012Green" => "toneCurve.green",
+        "crs:ToneCurvePV2012Blue" => "toneCurve.blue",
+        "crs:ParametricShadows" => "toneCurve.parametric.shadows",
+        "crs:ParametricDarks" => "toneCurve.parametric.darks",
+        "crs:ParametricLights" => "toneCurve.parametric.lights",
+        "crs:ParametricHighlights" => "toneCurve.parametric.highlights",
+        "crs:ParametricShadowSplit" => "toneCurve.parametric.shadowSplit",
+        "crs:ParametricMidtoneSplit" => "toneCurve.parametric.midtoneSplit",
+        "crs:ParametricHighlightSplit" => "toneCurve.parametric.highlightSplit",
+        "crs:SplitToningHighlightHue" => "splitToning.highlightHue",
+        "crs:SplitToningHighlightSaturation" => "splitToning.highlightSaturation",
+        "crs:SplitToningShadowHue" => "splitToning.shadowHue",
+        "crs:SplitToningShadowSaturation" => "splitToning.shadowSaturation",
+        "crs:SplitToningBalance" => "splitToning.balance",
+        "crs:LensProfileEnable" => "lens.profileEnabled",
+        "crs:LensProfileName" | "photocull:LensProfileName" => "lens.profileName",
+        "crs:LensProfileDistortionScale" => "lens.distortionScale",
+        "crs:LensProfileVignettingScale" => "lens.vignettingScale",
+        "crs:LensManualDistortionAmount" => "lens.distortion",
+        "crs:VignetteAmount" => "lens.vignetting",
+        "crs:ChromaticAberrationR" => "lens.caRed",
+        "crs:ChromaticAberrationB" => "lens.caBlue",
+        "crs:PostCropVignetteAmount" => "vignette.amount",
+        "crs:PostCropVignetteMidpoint" => "vignette.midpoint",
+        "crs:PostCropVignetteRoundness" => "vignette.roundness",
+        "crs:PostCropVignetteFeather" => "vignette.feather",
+        "crs:PostCropVignetteHighlightContrast" => "vignette.highlights",
+        "crs:GrainAmount" => "grain.amount",
+        "crs:GrainSize" => "grain.size",
+        "crs:GrainFrequency" => "grain.roughness",
+        "crs:GrainSeed" => "grain.seed",
+        "photocull:LookName" => "look.name",
+        "photocull:LookAmount" => "look.amount",
+        "photocull:LookInterpolation" => "look.interpolation",
+        _ => match field {
+            "hsl" | "grayMixer" => {
+                let name = key.strip_prefix("crs:")?;
+                let bands = [
+                    ("GrayMixer", "grayMixer"),
+                    ("HueAdjustment", "hsl.hue"),
+                    ("SaturationAdjustment", "hsl.saturation"),
+                    ("LuminanceAdjustment", "hsl.luminance"),
+                ];
+                let (path, band) = bands
+                    .iter()
+                    .find_map(|(prefix, path)| name.strip_prefix(prefix).map(|band| (*path, band)))?;
+                let i = HSL_BANDS.iter().position(|b| *b == band)?;
+                return Some(format!("{}.{}", path, i));
+            }
+            "toneCurve" | "splitToning" | "lens" | "vignette" | "grain" | "look" => return None,
+            _ => field,
+        },
+    };
+    Some(setting.to_string())
+}
+
+/// The `EditState` field (by its JSON name) an XMP setting belongs to.
+pub fn field_for_key(key: &str) -> Option<&'static str> {
+    let field = match key {
+        "xmp:Rating" => "rating",
+        "photocull:Flag" => "flag",
+        "crs:Exposure2012" | "crs:Exposure" => "exposure",
+        "crs:Contrast2012" | "crs:Contrast" => "contrast",
+        "crs:Highlights2012" => "highlights",
+        "crs:Shadows2012" => "shadows",
+        "photocull:HighlightReconstruction" => "highlightReconstruction",
+        "crs:Temperature" => "whiteBalanceTemp",
+        "crs:Tint" => "whiteBalanceTint",
+        "crs:Saturation" => "saturation",
+        "crs:Vibrance" => "vibrance",
+        "crs:Clarity2012" | "crs:Clarity" => "clarity",
+        "crs:Texture" => "texture",
+        "crs:Dehaze" => "dehaze",
+        "crs:Sharpness" => "sharpeningAmount",
+        "crs:SharpenRadius" => "sharpeningRadius",
+        "crs:SharpenDetail" => "sharpeningDetail",
+        "crs:SharpenEdgeMasking" => "sharpeningMasking",
+        "crs:LuminanceSmoothing" => "noiseReduction",
+        "crs:LuminanceNoiseReductionDetail" => "noiseReductionDetail",
+        "crs:ColorNoiseReduction" => "colorNoiseReduction",
+        "crs:ColorNoiseReductionDetail" => "colorNoiseDetail",
+        "crs:CropAngle" => "straightenAngle",
+        "crs:Orientation" => "rotation",
+        "crs:ConvertToGrayscale" => "monochrome",
+        "crs:VignetteAmount" | "crs:ChromaticAberrationR" | "crs:ChromaticAberrationB" => "lens",
+        "photocull:Masks" => "masks",
+        _ => {
+            let name = key.strip_prefix("crs:").or_else(|| key.strip_prefix("photocull:"))?;
+            let prefixes: &[(&str, &'static str)] = &[
+                ("Parametric", "toneCurve"),
+                ("ToneCurvePV2012", "toneCurve"),
+                ("HueAdjustment", "hsl"),
+                ("SaturationAdjustment", "hsl"),
+                ("LuminanceAdjustment", "hsl"),
+                ("GrayMixer", "grayMixer"),
+                ("SplitToning", "splitToning"),
+                ("Lens", "lens"),
+                ("PostCropVignette", "vignette"),
+                ("Grain", "grain"),
+            ];
+            if key.starts_with("photocull:Look") {
+                return Some("look");
+            }
+            return prefixes
+                .iter()
+                .find(|(prefix, _)| name.starts_with(prefix))
+                .map(|(_, field)| *field);
+        }
+    };
+    Some(field)
+}
+
+fn apply_attribute(state: &mut EditState, key: &str, value: &str) {
+    match key {
+        "xmp:Rating" => {
+            state.rating = value.parse().unwrap_or(0);
+        }
+        "crs:Exposure2012" | "crs:Exposure" => {
+            state.exposure = value.parse().unwrap_or(0.0);
+        }
+        "crs:Contrast2012" | "crs:Contrast" => {
+            state.contrast = value.parse().unwrap_or(0.0);
+        }
+        "crs:Highlights2012" => {
+            state.highlights = value.parse().unwrap_or(0.0);
+        }
+        "crs:Shadows2012" => {
+            state.shadows = value.parse().unwrap_or(0.0);
+        }
+        "crs:Temperature" => {
+            state.white_balance_temp = value.parse().unwrap_or(5500.0);
+        }
+        "crs:Tint" => {
+            state.white_balance_tint = value.parse().unwrap_or(0.0);
+        }
+        "crs:Saturation" => {
+            state.saturation = value.parse().unwrap_or(0.0);
+        }
+        "crs:Vibrance" => {
+            state.vibrance = value.parse().unwrap_or(0.0);
+        }
+        "crs:Clarity2012" | "crs:Clarity" => {
+            state.clarity = value.parse().unwrap_or(0.0);
+        }
+        "crs:Texture" => {
+            state.texture = value.parse().unwrap_or(0.0);
+        }
+        "crs:Dehaze" => {
+            state.dehaze = value.parse().unwrap_or(0.0);
+        }
+        "crs:Sharpness" => {
+            state.sharpening_amount = value.parse().unwrap_or(0.0);
+        }
+        "crs:SharpenRadius" => {
+            state.sharpening_radius = value.parse().unwrap_or(1.0);
+        }
+        "crs:SharpenDetail" => {
+            state.sharpening_detail = value.parse().unwrap_or(25.0);
+        }
+        "crs:SharpenEdgeMasking" => {
+            state.sharpening_masking = value.parse().unwrap_or(0.0);
+        }
+        "crs:LuminanceSmoothing" => {
+            state.noise_reduction = value.parse().unwrap_or(0.0);
+        }
+        "crs:LuminanceNoiseReductionDetail" => {
+            state.noise_reduction_detail = value.parse().unwrap_or(50.0);
+        }
+        "crs:ColorNoiseReduction" => {
+            state.color_noise_reduction = value.parse().unwrap_or(0.0);
+        }
+        "crs:ColorNoiseReductionDetail" => {
+            state.color_noise_detail = value.parse().unwrap_or(50.0);
+        }
+        "crs:CropAngle" => {
+            state.straighten_angle = value.parse().unwrap_or(0.0);
+        }
+        "crs:Orientation" => {
+            state.rotation = match value {
+                "6" => 90,
+                "3" => 180,
+                "8" => 270,
+                _ => 0,
+            };
+        }
+        "crs:ParametricShadows" => {
+            state.tone_curve.parametric.shadows = value.parse().unwrap_or(0.0);
+        }
+        "crs:ParametricDarks" => {
+            state.tone_curve.parametric.darks = value.parse().unwrap_or(0.0);
+        }
+        "crs:ParametricLights" => {
+            state.tone_curve.parametric.lights = value.parse().unwrap_or(0.0);
+        }
+        "crs:ParametricHighlights" => {
+            state.tone_curve.parametric.highlights = value.parse().unwrap_or(0.0);
+        }
+        "crs:ParametricShadowSplit" => {
+            state.tone_curve.parametric.shadow_split = value.parse().unwrap_or(25.0);
+        }
+        "crs:ParametricMidtoneSplit" => {
+            state.tone_curve.parametric.midtone_split = value.parse().unwrap_or(50.0);
+        }
+        "crs:ParametricHighlightSplit" => {
+            state.tone_curve.parametric.highlight_split = value.parse().unwrap_or(75.0);
+        }
+        "crs:LensProfileEnable" => {
+            state.lens.profile_enabled = value == "1";
+        }
+        "photocull:LensProfileName" => {
+            state.lens.profile_name = (!value.is_empty()).then(|| value.to_string());
+        }
+        // Lightroom's own profile names; older sidecars kept the lensfun
+        // name here, so it is used unless the photocull name is present
+        "crs:LensProfileName" => {
+            if state.lens.profile_name.is_none() && !value.is_empty() {
+                state.lens.profile_name = Some(value.to_string());
+            }
+        }
+        "crs:LensProfileDistortionScale" => {
+            state.lens.distortion_scale = value.parse().unwrap_or(100.0);
+        }
+        "crs:LensProfileVignettingScale" => {
+            state.lens.vignetting_scale = value.parse().unwrap_or(100.0);
+        }
+        "crs:LensManualDistortionAmount" => {
+            state.lens.distortion = value.parse().unwrap_or(0.0);
+        }
+        "crs:VignetteAmount" => {
+            state.lens.vignetting = value.parse().unwrap_or(0.0);
+        }
+        "crs:ChromaticAberrationR" => {
+            state.lens.ca_red = value.parse().unwrap_or(0.0);
+        }
+        "crs:ChromaticAberrationB" => {
+            state.lens.ca_blue = value.parse().unwrap_or(0.0);
+        }
+        "crs:PostCropVignetteAmount" => {
+            state.vignette.amount = value.parse().unwrap_or(0.0);
+        }
+        "crs:PostCropVignetteMidpoint" => {
+            state.vignette.midpoint = value.parse().unwrap_or(50.0);
+        }
+        "crs:PostCropVignetteRoundness" => {
+            state.vignette.roundness = value.parse().unwrap_or(0.0);
+        }
+        "crs:PostCropVignetteFeather" => {
+            state.vignette.feather = value.parse().unwrap_or(50.0);
+        }
+        "crs:PostCropVignetteHighlightContrast" => {
+            state.vignette.highlights = value.parse().unwrap_or(0.0);
+        }
+        "crs:GrainAmount" => {
+            state.grain.amount = value.parse().unwrap_or(0.0);
+        }
+        "crs:GrainSize" => {
+            state.grain.size = value.parse().unwrap_or(25.0);
+        }
+        "crs:GrainFrequency" => {
+            state.grain.roughness = value.parse().unwrap_or(50.0);
+        }
+        "crs:GrainSeed" => {
+            state.grain.seed = value.parse().unwrap_or(0);
+        }
+        "crs:ConvertToGrayscale" => {
+            state.monochrome = value.eq_ignore_ascii_case("true");
+        }
+        "crs:SplitToningHighlightHue" => {
+            state.split_toning.highlight_hue = value.parse().unwrap_or(0.0);
+        }
+        "crs:SplitToningHighlightSaturation" => {
+            state.split_toning.highlight_saturation = value.parse().unwrap_or(0.0);
+        }
+        "crs:SplitToningShadowHue" => {
+            state.split_toning.shadow_hue = value.parse().unwrap_or(0.0);
+        }
+        "crs:SplitToningShadowSaturation" => {
+            state.split_toning.shadow_saturation = value.parse().unwrap_or(0.0);
+        }
+        "crs:SplitToningBalance" => {
+            state.split_toning.balance = value.parse().unwrap_or(0.0);
+        }
+        "photocull:LookName" => {
+            state.look.name = (!value.is_empty()).then(|| value.to_string());
+        }
+        "photocull:LookAmount" => {
+            state.look.amount = value.parse().unwrap_or(100.0);
+        }
+        "photocull:LookInterpolation" => {
+            state.look.interpolation = match value {
+                "trilinear" => LutInterpolation::Trilinear,
+                _ => LutInterpolation::Tetrahedral,
+            };
+        }
+        "photocull:Masks" => match serde_json::from_str(value) {
+            Ok(masks) => state.masks = masks,
+            Err(e) => tracing::warn!("Ignoring unreadable masks in XMP: {}", e),
+        },
+        "photocull:HighlightReconstruction" => {
+            state.highlight_reconstruction = match value {
+                "clip" => HighlightMode::Clip,
+                "blend" => HighlightMode::Blend,
+                _ => HighlightMode::Reconstruct,
+            };
+        }
+        "photocull:Flag" => {
+            state.flag = match value {
+                "pick" => Flag::Pick,
+                "reject" => Flag::Reject,
+                _ => Flag::None,
+            };
+        }
+        _ => apply_hsl_attribute(state, key, value),
+    }
+}
+
+fn apply_hsl_attribute(state: &mut EditState, key: &str, value: &str) {
+    let Some(name) = key.strip_prefix("crs:") else {
+        return;
+    };
+    let (target, band) = if let Some(band) = name.strip_prefix("GrayMixer") {
+        (&mut state.gray_mixer, band)
+    } else if let Some(band) = name.strip_prefix("HueAdjustment") {
+        (&mut state.hsl.hue, band)
+    } else if let Some(band) = name.strip_prefix("SaturationAdjustment") {
+        (&mut state.hsl.saturation, band)
+    } else if let Some(band) = name.strip_prefix("LuminanceAdjustment") {
+        (&mut state.hsl.luminance, band)
+    } else {
+        return;
+    };
+    if let Some(i) = HSL_BANDS.iter().position(|b| *b == band) {
+        target[i] = value.parse().unwrap_or(0.0);
+    }
+}
+
+fn apply_sequence(state: &mut EditState, key: &str, items: &[String]) {
+    match key {
+        "crs:ToneCurvePV2012" => {
+            state.tone_curve.master = parse_curve_points(items);
+        }
+        "crs:ToneCurvePV2012Red" => {
+            state.tone_curve.red = parse_curve_points(items);
+        }
+        "crs:ToneCurvePV2012Green" => {
+            state.tone_curve.green = parse_curve_points(items);
+        }
+        "crs:ToneCurvePV2012Blue" => {
+            state.tone_curve.blue = parse_curve_points(items);
+        }
+        _ => {}
+    }
 }
 
 pub fn write_xmp(state: &EditState) -> Result<String, String> {
+    write_document(state, None)
+}
+
+/// Writes a Lightroom-style preset holding only the given settings, each an
+/// `EditState` field or a path within one (see `setting_for_key`).
+pub fn write_preset_xmp(name: &str, state: &EditState, settings: &[String]) -> Result<String, String> {
+    write_document(state, Some((name, settings)))
+}
+
+fn write_document(state: &EditState, preset: Option<(&str, &[String])>) -> Result<String, String> {
     let mut writer = Writer::new(Cursor::new(Vec::new()));
 
     writer.write_event(Event::Decl(quick_xml::events::BytesDecl::new("1.0", Some("UTF-8"), None)))
@@ -106,12 +552,23 @@ pub fn write_xmp(state: &EditState) -> Result<String, String> {
     desc.push_attribute(("xmp:Rating", state.rating.to_string().as_str()));
     desc.push_attribute(("crs:Exposure2012", format!("{:+.2}", state.exposure).as_str()));
     desc.push_attribute(("crs:Contrast2012", state.contrast.to_string().as_str()));
+    desc.push_attribute(("crs:Highlights2012", state.highlights.to_string().as_str()));
+    desc.push_attribute(("crs:Shadows2012", state.shadows.to_string().as_str()));
     desc.push_attribute(("crs:Temperature", state.white_balance_temp.to_string().as_str()));
     desc.push_attribute(("crs:Tint", state.white_balance_tint.to_string().as_str()));
     desc.push_attribute(("crs:Saturation", state.saturation.to_string().as_str()));
     desc.push_attribute(("crs:Vibrance", state.vibrance.to_string().as_str()));
+    desc.push_attribute(("crs:Clarity2012", state.clarity.to_string().as_str()));
+    desc.push_attribute(("crs:Texture", state.texture.to_string().as_str()));
+    desc.push_attribute(("crs:Dehaze", state.dehaze.to_string().as_str()));
     desc.push_attribute(("crs:Sharpness", state.sharpening_amount.to_string().as_str()));
     desc.push_attribute(("crs:SharpenRadius", state.sharpening_radius.to_string().as_str()));
+    desc.push_attribute(("crs:SharpenDetail", state.sharpening_detail.to_string().as_str()));
+    desc.push_attribute(("crs:SharpenEdgeMasking", state.sharpening_masking.to_string().as_str()));
+    desc.push_attribute(("crs:LuminanceSmoothing", state.noise_reduction.to_string().as_str()));
+    desc.push_attribute(("crs:LuminanceNoiseReductionDetail", state.noise_reduction_detail.to_string().as_str()));
+    desc.push_attribute(("crs:ColorNoiseReduction", state.color_noise_reduction.to_string().as_str()));
+    desc.push_attribute(("crs:ColorNoiseReductionDetail", state.color_noise_detail.to_string().as_str()));
     desc.push_attribute(("crs:CropAngle", state.straighten_angle.to_string().as_str()));
 
     let orientation = match state.rotation {
@@ -122,6 +579,62 @@ pub fn write_xmp(state: &EditState) -> Result<String, String> {
     };
     desc.push_attribute(("crs:Orientation", orientation));
 
+    let parametric = &state.tone_curve.parametric;
+    desc.push_attribute(("crs:ParametricShadows", parametric.shadows.to_string().as_str()));
+    desc.push_attribute(("crs:ParametricDarks", parametric.darks.to_string().as_str()));
+    desc.push_attribute(("crs:ParametricLights", parametric.lights.to_string().as_str()));
+    desc.push_attribute(("crs:ParametricHighlights", parametric.highlights.to_string().as_str()));
+    desc.push_attribute(("crs:ParametricShadowSplit", parametric.shadow_split.to_string().as_str()));
+    desc.push_attribute(("crs:ParametricMidtoneSplit", parametric.midtone_split.to_string().as_str()));
+    desc.push_attribute(("crs:ParametricHighlightSplit", parametric.highlight_split.to_string().as_str()));
+
+    for (i, band) in HSL_BANDS.iter().enumerate() {
+        desc.push_attribute((format!("crs:HueAdjustment{}", band).as_str(), state.hsl.hue[i].to_string().as_str()));
+        desc.push_attribute((format!("crs:SaturationAdjustment{}", band).as_str(), state.hsl.saturation[i].to_string().as_str()));
+        desc.push_attribute((format!("crs:LuminanceAdjustment{}", band).as_str(), state.hsl.luminance[i].to_string().as_str()));
+    }
+
+    desc.push_attribute(("crs:ConvertToGrayscale", if state.monochrome { "True" } else { "False" }));
+    for (i, band) in HSL_BANDS.iter().enumerate() {
+        desc.push_attribute((format!("crs:GrayMixer{}", band).as_str(), state.gray_mixer[i].to_string().as_str()));
+    }
+
+    let toning = &state.split_toning;
+    desc.push_attribute(("crs:SplitToningHighlightHue", toning.highlight_hue.to_string().as_str()));
+    desc.push_attribute(("crs:SplitToningHighlightSaturation", toning.highlight_saturation.to_string().as_str()));
+    desc.push_attribute(("crs:SplitToningShadowHue", toning.shadow_hue.to_string().as_str()));
+    desc.push_attribute(("crs:SplitToningShadowSaturation", toning.shadow_saturation.to_string().as_str()));
+    desc.push_attribute(("crs:SplitToningBalance", toning.balance.to_string().as_str()));
+
+    let lens = &state.lens;
+    desc.push_attribute(("crs:LensProfileEnable", if lens.profile_enabled { "1" } else { "0" }));
+    if let Some(name) = &lens.profile_name {
+        desc.push_attribute(("photocull:LensProfileName", name.as_str()));
+    }
+    desc.push_attribute(("crs:LensProfileDistortionScale", lens.distortion_scale.to_string().as_str()));
+    desc.push_attribute(("crs:LensProfileVignettingScale", lens.vignetting_scale.to_string().as_str()));
+    desc.push_attribute(("crs:LensManualDistortionAmount", lens.distortion.to_string().as_str()));
+    desc.push_attribute(("crs:VignetteAmount", lens.vignetting.to_string().as_str()));
+    desc.push_attribute(("crs:ChromaticAberrationR", lens.ca_red.to_string().as_str()));
+    desc.push_attribute(("crs:ChromaticAberrationB", lens.ca_blue.to_string().as_str()));
+
+    let vignette = &state.vignette;
+    desc.push_attribute(("crs:PostCropVignetteAmount", vignette.amount.to_string().as_str()));
+    desc.push_attribute(("crs:PostCropVignetteMidpoint", vignette.midpoint.to_string().as_str()));
+    desc.push_attribute(("crs:PostCropVignetteRoundness", vignette.roundness.to_string().as_str()));
+    desc.push_attribute(("crs:PostCropVignetteFeather", vignette.feather.to_string().as_str()));
+    desc.push_attribute(("crs:PostCropVignetteHighlightContrast", vignette.highlights.to_string().as_str()));
+    // Highlight priority
+    desc.push_attribute(("crs:PostCropVignetteStyle", "1"));
+
+    let grain = &state.grain;
+    desc.push_attribute(("crs:GrainAmount", grain.amount.to_string().as_str()));
+    desc.push_attribute(("crs:GrainSize", grain.size.to_string().as_str()));
+    desc.push_attribute(("crs:GrainFrequency", grain.roughness.to_string().as_str()));
+    if grain.seed != 0 {
+        desc.push_attribute(("crs:GrainSeed", grain.seed.to_string().as_str()));
+    }
+
     let flag_str = match state.flag {
         Flag::Pick => "pick",
         Flag::Reject => "reject",
@@ -129,8 +642,67 @@ pub fn write_xmp(state: &EditState) -> Result<String, String> {
     };
     desc.push_attribute(("photocull:Flag", flag_str));
 
-    writer.write_event(Event::Empty(desc)).map_err(|e| e.to_string())?;
+    let highlight_mode = match state.highlight_reconstruction {
+        HighlightMode::Clip => "clip",
+        HighlightMode::Blend => "blend",
+        HighlightMode::Reconstruct => "reconstruct",
+    };
+    desc.push_attribute(("photocull:HighlightReconstruction", highlight_mode));
+
+    if let Some(name) = &state.look.name {
+        desc.push_attribute(("photocull:LookName", name.as_str()));
+        desc.push_attribute(("photocull:LookAmount", state.look.amount.to_string().as_str()));
+        let interpolation = match state.look.interpolation {
+            LutInterpolation::Trilinear => "trilinear",
+            LutInterpolation::Tetrahedral => "tetrahedral",
+        };
+        desc.push_attribute(("photocull:LookInterpolation", interpolation));
+    }
+
+    if !state.masks.is_empty() {
+        let masks = serde_json::to_string(&state.masks).map_err(|e| e.to_string())?;
+        desc.push_attribute(("photocull:Masks", masks.as_str()));
+    }
+
+    let included = |key: &str| match preset {
+        Some((_, settings)) => setting_for_key(key).is_some_and(|setting| {
+            settings
+                .iter()
+                .any(|s| setting == *s || setting.strip_prefix(s.as_str()).is_some_and(|rest| rest.starts_with('.')))
+        }),
+        None => true,
+    };
+    if let Some((name, settings)) = preset {
+        let mut filtered = BytesStart::new("rdf:Description");
+        for attr in desc.attributes().filter_map(|a| a.ok()) {
+            let key = String::from_utf8_lossy(attr.key.as_ref()).to_string();
+            if key.starts_with("xmlns:") || included(&key) {
+                filtered.push_attribute(attr);
+            }
+        }
+        filtered.push_attribute(("crs:PresetType", "Normal"));
+        filtered.push_attribute(("crs:HasSettings", "True"));
+        filtered.push_attribute(("crs:Name", name));
+        filtered.push_attribute(("photocull:PresetFields", settings.join(",").as_str()));
+        desc = filtered;
+    }
+
+    writer.write_event(Event::Start(desc)).map_err(|e| e.to_string())?;
+
+    let curve = &state.tone_curve;
+    let curves = [
+        ("crs:ToneCurvePV2012", &curve.master),
+        ("crs:ToneCurvePV2012Red", &curve.red),
+        ("crs:ToneCurvePV2012Green", &curve.green),
+        ("crs:ToneCurvePV2012Blue", &curve.blue),
+    ];
+    for (name, points) in curves {
+        if included(name) {
+            write_seq(&mut writer, name, &format_curve_points(points))?;
+        }
+    }
 
+    writer.write_event(Event::End(BytesEnd::new("rdf:Description"))).map_err(|e| e.to_string())?;
     writer.write_event(Event::End(BytesEnd::new("rdf:RDF"))).map_err(|e| e.to_string())?;
     writer.write_event(Event::End(BytesEnd::new("x:xmpmeta"))).map_err(|e| e.to_string())?;
 
@@ -138,7 +710,245 @@ pub fn write_xmp(state: &EditState) -> Result<String, String> {
     String::from_utf8(result).map_err(|e| e.to_string())
 }
 
+fn write_seq(writer: &mut Writer<Cursor<Vec<u8>>>, name: &str, items: &[String]) -> Result<(), String> {
+    write_container(writer, name, "rdf:Seq", items)
+}
+
+fn write_container(
+    writer: &mut Writer<Cursor<Vec<u8>>>,
+    name: &str,
+    container: &str,
+    items: &[String],
+) -> Result<(), String> {
+    writer.write_event(Event::Start(BytesStart::new(name))).map_err(|e| e.to_string())?;
+    writer.write_event(Event::Start(BytesStart::new(container))).map_err(|e| e.to_string())?;
+    for item in items {
+        writer.write_event(Event::Start(BytesStart::new("rdf:li"))).map_err(|e| e.to_string())?;
+        writer.write_event(Event::Text(BytesText::new(item))).map_err(|e| e.to_string())?;
+        writer.write_event(Event::End(BytesEnd::new("rdf:li"))).map_err(|e| e.to_string())?;
+    }
+    writer.write_event(Event::End(BytesEnd::new(container))).map_err(|e| e.to_string())?;
+    writer.write_event(Event::End(BytesEnd::new(name))).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Title, keywords and copyright notice, kept in Dublin Core properties.
+#[derive(Debug, Clone, Default)]
+pub struct Description {
+    pub title: Option<String>,
+    pub keywords: Vec<String>,
+    pub rights: Option<String>,
+}
+
+/// Reads `dc:title`, `dc:subject` and `dc:rights` from a sidecar or an XMP
+/// packet embedded in an image.
+pub fn parse_description(content: &str) -> Description {
+    let mut description = Description::default();
+    let mut reader = Reader::from_str(content);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut property: Option<Vec<u8>> = None;
+    let mut items: Vec<String> = Vec::new();
+    let mut in_item = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => match e.name().as_ref() {
+                name @ (b"dc:title" | b"dc:subject" | b"dc:rights") => {
+                    property = Some(name.to_vec());
+                    items.clear();
+                }
+                b"rdf:li" => in_item = true,
+                _ => {}
+            },
+            Ok(Event::Text(t)) if in_item && property.is_some() => {
+                if let Ok(text) = t.unescape() {
+                    items.push(text.trim().to_string());
+                }
+            }
+            Ok(Event::End(e)) => {
+                if e.name().as_ref() == b"rdf:li" {
+                    in_item = false;
+                } else if property.as_deref() == Some(e.name().as_ref()) {
+                    // Title and rights are rdf:Alt; the first entry is x-default
+                    let first = items.first().filter(|t| !t.is_empty()).cloned();
+                    match property.take().as_deref() {
+                        Some(b"dc:title") => description.title = first,
+                        Some(b"dc:rights") => description.rights = first,
+                        _ => description.keywords = items.drain(..).filter(|k| !k.is_empty()).collect(),
+                    }
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    description
+}
+
+/// Writes the XMP packet embedded in exported images.
+pub fn write_export_xmp(rating: Option<u8>, description: &Description) -> Result<String, String> {
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+
+    writer
+        .write_event(Event::PI(BytesText::from_escaped(
+            "xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"",
+        )))
+        .map_err(|e| e.to_string())?;
+
+    let mut xmpmeta = BytesStart::new("x:xmpmeta");
+    xmpmeta.push_attribute(("xmlns:x", "adobe:ns:meta/"));
+    writer.write_event(Event::Start(xmpmeta)).map_err(|e| e.to_string())?;
+
+    let mut rdf = BytesStart::new("rdf:RDF");
+    rdf.push_attribute(("xmlns:rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#"));
+    writer.write_event(Event::Start(rdf)).map_err(|e| e.to_string())?;
+
+    let mut desc = BytesStart::new("rdf:Description");
+    desc.push_attribute(("rdf:about", ""));
+    desc.push_attribute(("xmlns:xmp", "http://ns.adobe.com/xap/1.0/"));
+    desc.push_attribute(("xmlns:dc", "http://purl.org/dc/elements/1.1/"));
+    if let Some(rating) = rating {
+        desc.push_attribute(("xmp:Rating", rating.to_string().as_str()));
+    }
+    writer.write_event(Event::Start(desc)).map_err(|e| e.to_string())?;
+
+    if let Some(title) = &description.title {
+        write_alt(&mut writer, "dc:title", title)?;
+    }
+    if !description.keywords.is_empty() {
+        write_container(&mut writer, "dc:subject", "rdf:Bag", &description.keywords)?;
+    }
+    if let Some(rights) = &description.rights {
+        write_alt(&mut writer, "dc:rights", rights)?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::new("rdf:Description"))).map_err(|e| e.to_string())?;
+    writer.write_event(Event::End(BytesEnd::new("rdf:RDF"))).map_err(|e| e.to_string())?;
+    writer.write_event(Event::End(BytesEnd::new("x:xmpmeta"))).map_err(|e| e.to_string())?;
+    writer
+        .write_event(Event::PI(BytesText::from_escaped("xpacket end=\"w\"")))
+        .map_err(|e| e.to_string())?;
+
+    let result = writer.into_inner().into_inner();
+    String::from_utf8(result).map_err(|e| e.to_string())
+}
+
+fn write_alt(writer: &mut Writer<Cursor<Vec<u8>>>, name: &str, value: &str) -> Result<(), String> {
+    writer.write_event(Event::Start(BytesStart::new(name))).map_err(|e| e.to_string())?;
+    writer.write_event(Event::Start(BytesStart::new("rdf:Alt"))).map_err(|e| e.to_string())?;
+    let mut item = BytesStart::new("rdf:li");
+    item.push_attribute(("xml:lang", "x-default"));
+    writer.write_event(Event::Start(item)).map_err(|e| e.to_string())?;
+    writer.write_event(Event::Text(BytesText::new(value))).map_err(|e| e.to_string())?;
+    writer.write_event(Event::End(BytesEnd::new("rdf:li"))).map_err(|e| e.to_string())?;
+    writer.write_event(Event::End(BytesEnd::new("rdf:Alt"))).map_err(|e| e.to_string())?;
+    writer.write_event(Event::End(BytesEnd::new(name))).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
 pub fn save_xmp_file(path: &str, state: &EditState) -> Result<(), String> {
     let xmp_content = write_xmp(state)?;
     std::fs::write(path, xmp_content).map_err(|e| format!("Write failed: {}", e))
 }
+
+/// Writes several sidecars so that either all of them change or none do:
+/// each goes to a temporary file first, renamed once every write succeeded.
+/// If a rename fails, the sidecars already replaced get their previous
+/// contents back. Paths must be distinct.
+pub fn save_xmp_files(entries: &[(String, EditState)]) -> Result<(), String> {
+    let mut temps: Vec<String> = Vec::new();
+    for (path, state) in entries {
+        let temp = format!("{}.tmp", path);
+        let written = write_xmp(state)
+            .and_then(|content| std::fs::write(&temp, content).map_err(|e| format!("Write failed: {}", e)));
+        if let Err(e) = written {
+            for t in temps.iter().chain(std::iter::once(&temp)) {
+                let _ = std::fs::remove_file(t);
+            }
+            return Err(e);
+        }
+        temps.push(temp);
+    }
+
+    // `None` for sidecars that do not exist yet
+    let previous: Vec<Option<Vec<u8>>> = entries.iter().map(|(path, _)| std::fs::read(path).ok()).collect();
+    for (index, ((path, _), temp)) in entries.iter().zip(&temps).enumerate() {
+        if let Err(e) = std::fs::rename(temp, path) {
+            for ((path, _), contents) in entries.iter().zip(&previous).take(index) {
+                let restored = match contents {
+                    Some(contents) => std::fs::write(path, contents),
+                    None => std::fs::remove_file(path),
+                };
+                if let Err(e) = restored {
+                    tracing::error!("Failed to restore {}: {}", path, e);
+                }
+            }
+            for temp in &temps[index..] {
+                let _ = std::fs::remove_file(temp);
+            }
+            return Err(format!("Write failed: {}", e));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_empty_description_is_not_read_as_settings() {
+        let preset = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="Adobe XMP Core 7.0-c000 1.000000">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:crs="http://ns.adobe.com/camera-raw-settings/1.0/"
+   crs:PresetType="Normal"
+   crs:Version="15.0"
+   crs:Exposure2012="+0.50"
+   crs:Contrast2012="+10">
+   <crs:Name>
+    <rdf:Alt>
+     <rdf:li xml:lang="x-default">Warm Matte</rdf:li>
+    </rdf:Alt>
+   </crs:Name>
+   <crs:Look>
+    <rdf:Description
+     crs:Name="Adobe Color"
+     crs:Amount="1"
+     crs:Exposure2012="-3.00"
+     crs:Contrast2012="-50"
+     crs:Saturation="-40"/>
+   </crs:Look>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+
+        let parsed = parse_preset_xmp(preset).unwrap();
+        assert_eq!(parsed.name.as_deref(), Some("Warm Matte"));
+        assert_eq!(parsed.state.exposure, 0.5);
+        assert_eq!(parsed.state.contrast, 10.0);
+        assert_eq!(parsed.state.saturation, 0.0);
+        assert!(!parsed.fields.iter().any(|f| f == "saturation"));
+    }
+
+    #[test]
+    fn grouped_keys_set_single_values() {
+        let preset = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:crs="http://ns.adobe.com/camera-raw-settings/1.0/"
+   crs:PresetType="Normal"
+   crs:HueAdjustmentOrange="-20"
+   crs:PostCropVignetteAmount="-15"
+   crs:LensProfileSetup="LensDefaults"/>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+
+        let parsed = parse_preset_xmp(preset).unwrap();
+        assert_eq!(parsed.fields, ["hsl", "vignette"]);
+        assert_eq!(parsed.settings, ["hsl.hue.1", "vignette.amount"]);
+    }
+}